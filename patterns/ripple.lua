@@ -0,0 +1,49 @@
+require "util"
+
+options.color = {
+  default = 0xffffff,
+  desc = "Color"
+}
+
+options.speed = {
+  default = 2.0,
+  desc = "Cells the ripple travels per second"
+}
+
+options.wavelength = {
+  default = 3.0,
+  desc = "Cells between ripples"
+}
+
+local distance = {}
+
+function setup()
+  t = 0
+
+  -- Ripples spread from the middle of the group
+  local cx, cy = 0, 0
+
+  for i = 1, #positions do
+    cx = cx + positions[i].x / #positions
+    cy = cy + positions[i].y / #positions
+  end
+
+  for i = 1, element_count do
+    local p = positions[i] or {x = cx, y = cy}
+    distance[i] = math.sqrt((p.x - cx) ^ 2 + (p.y - cy) ^ 2)
+  end
+end
+
+function update(dt)
+  t = t + dt
+  local values = {}
+
+  for i = 1, element_count do
+    local phase = 2 * math.pi * (distance[i] - options.speed.value * t) / options.wavelength.value
+    local alpha = 0.5 * math.cos(phase) + 0.5
+    local r, g, b = int_as_rgb(options.color.value)
+    values[i] = rgb_as_int(r * alpha, g * alpha, b * alpha)
+  end
+
+  return values
+end
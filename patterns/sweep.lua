@@ -0,0 +1,35 @@
+options.center = {
+  default = 0,
+  desc = "Position at the middle of the sweep, in degrees"
+}
+
+options.width = {
+  default = 90,
+  desc = "Degrees either side of the center"
+}
+
+options.frequency = {
+  default = 0.25,
+  desc = "Sweeps per second"
+}
+
+options.spread = {
+  default = 0.5,
+  desc = "Phase offset between elements"
+}
+
+function setup()
+  t = 0
+end
+
+function update(dt)
+  t = t + dt
+  local values = {}
+
+  for i = 1, element_count do
+    local phase = 2 * math.pi * options.frequency.value * t - (i - 1) * options.spread.value
+    values[i] = math.floor(options.center.value + options.width.value * math.sin(phase) + 0.5)
+  end
+
+  return values
+end
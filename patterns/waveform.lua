@@ -1,4 +1,4 @@
-require "patterns/util"
+require "util"
 
 options.color = {
   default = 0xffffff,
@@ -0,0 +1,352 @@
+use std::io;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use crate::output::DmxOutput;
+use crate::dmx_input::{DmxInput, RECEIVE_TIMEOUT};
+use crate::patch::Universes;
+
+pub const ARTNET_PORT: u16 = 6454;
+/// Port-addresses are 15 bits: net, sub-net and universe
+pub const MAX_PORT_ADDRESS: u16 = 0x7fff;
+
+const ARTNET_ID: &[u8; 8] = b"Art-Net\0";
+const PROTOCOL_VERSION: u16 = 14;
+const OP_POLL: u16 = 0x2000;
+const OP_POLL_REPLY: u16 = 0x2100;
+const OP_DMX: u16 = 0x5000;
+
+const SHORT_NAME: &str = "Lightboard";
+const LONG_NAME: &str = "Lightboard-rs DMX lighting controller";
+
+/// Sends ArtDmx packets to an Art-Net node and answers ArtPoll requests from
+/// other controllers on the network. The universe given when creating the
+/// node is the port-address for patch universe 1.
+pub struct ArtNetNode {
+    socket: UdpSocket,
+    target: SocketAddr,
+    universe: u16,
+    sequences: HashMap<u16, u8>,
+    local_ip: Ipv4Addr,
+}
+
+impl ArtNetNode {
+    pub fn new(bind: &str, target: &str, universe: u16) -> io::Result<Self> {
+        let target = resolve(target)?;
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_broadcast(true)?;
+        socket.set_nonblocking(true)?;
+
+        let local_ip = match socket.local_addr()?.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() => ip,
+            _ => route_ip(target).unwrap_or(Ipv4Addr::UNSPECIFIED),
+        };
+
+        Ok(Self {
+            socket,
+            target,
+            universe,
+            sequences: HashMap::new(),
+            local_ip,
+        })
+    }
+
+    pub fn send_universe(&mut self, universe: u16, data: &[u8]) -> io::Result<()> {
+        self.poll()?;
+
+        // Sequence 0 disables reordering on the receiver, so skip it
+        let sequence = self.sequences.entry(universe).or_insert(0);
+        *sequence = sequence.wrapping_add(1).max(1);
+        let packet = build_art_dmx(universe, *sequence, data);
+        self.socket.send_to(&packet, self.target)?;
+        Ok(())
+    }
+
+    /// Answer any ArtPoll requests that have arrived since the last call.
+    pub fn poll(&mut self) -> io::Result<()> {
+        let mut buf = [0; 1024];
+
+        loop {
+            let (len, src) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            if opcode(&buf[..len]) == Some(OP_POLL) {
+                let reply = build_art_poll_reply(self.local_ip, self.universe);
+                self.socket.send_to(&reply, src)?;
+            }
+        }
+    }
+}
+
+impl DmxOutput for ArtNetNode {
+    /// Send every patched universe, offset by the configured port-address.
+    /// Any past the last port-address are left out, which check reports.
+    fn send(&mut self, universes: &Universes) -> io::Result<()> {
+        for (universe, data) in universes.iter() {
+            let port_address = match self.universe.checked_add(universe - 1) {
+                Some(port_address) if port_address <= MAX_PORT_ADDRESS => port_address,
+                _ => continue,
+            };
+
+            self.send_universe(port_address, data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Receives ArtDmx packets from a console. Port-address `universe` is
+/// received as patch universe 1, and so on for `count` universes.
+pub struct ArtNetReceiver {
+    socket: UdpSocket,
+    universe: u16,
+    count: u16,
+}
+
+impl ArtNetReceiver {
+    pub fn new(bind: &str, universe: u16, count: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+        Ok(Self { socket, universe, count })
+    }
+}
+
+impl DmxInput for ArtNetReceiver {
+    fn receive(&mut self) -> io::Result<Option<(u16, Vec<u8>)>> {
+        let mut buf = [0; 1024];
+
+        let len = match self.socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                       || e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        match parse_art_dmx(&buf[..len]) {
+            Some((universe, data)) if universe >= self.universe
+                                   && universe - self.universe < self.count => {
+                Ok(Some((universe - self.universe + 1, data.to_vec())))
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    let addr = if addr.contains(':') {
+        addr.to_owned()
+    } else {
+        format!("{}:{}", addr, ARTNET_PORT)
+    };
+
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "No address for Art-Net node")
+    })
+}
+
+// Find the interface address used to reach the target, for the ArtPollReply
+fn route_ip(target: SocketAddr) -> Option<Ipv4Addr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect(target).ok()?;
+
+    match socket.local_addr().ok()?.ip() {
+        IpAddr::V4(ip) => Some(ip),
+        IpAddr::V6(_) => None,
+    }
+}
+
+fn header(op: u16) -> Vec<u8> {
+    let mut packet = ARTNET_ID.to_vec();
+    packet.extend_from_slice(&op.to_le_bytes());
+    packet
+}
+
+pub fn opcode(packet: &[u8]) -> Option<u16> {
+    if packet.len() < 10 || &packet[..8] != ARTNET_ID {
+        return None;
+    }
+
+    Some(u16::from_le_bytes([packet[8], packet[9]]))
+}
+
+pub fn build_art_dmx(universe: u16, sequence: u8, data: &[u8]) -> Vec<u8> {
+    // Length must be even and between 2 and 512
+    let mut length = data.len().clamp(2, 512);
+    length += length % 2;
+
+    let mut packet = header(OP_DMX);
+    packet.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+    packet.push(sequence);
+    packet.push(0);                                 // Physical port
+    packet.push((universe & 0xff) as u8);           // SubUni
+    packet.push(((universe >> 8) & 0x7f) as u8);    // Net
+    packet.extend_from_slice(&(length as u16).to_be_bytes());
+
+    let start = packet.len();
+    packet.resize(start + length, 0);
+    let count = data.len().min(length);
+    packet[start..start + count].copy_from_slice(&data[..count]);
+
+    packet
+}
+
+/// The port-address and DMX data of an ArtDmx packet.
+pub fn parse_art_dmx(packet: &[u8]) -> Option<(u16, &[u8])> {
+    if opcode(packet) != Some(OP_DMX) || packet.len() < 18 {
+        return None;
+    }
+
+    let universe = u16::from_le_bytes([packet[14], packet[15] & 0x7f]);
+    let length = u16::from_be_bytes([packet[16], packet[17]]) as usize;
+    let data = packet.get(18..18 + length.min(512))?;
+    Some((universe, data))
+}
+
+fn push_str(packet: &mut Vec<u8>, s: &str, len: usize) {
+    let mut field = s.as_bytes().to_vec();
+    field.truncate(len - 1);    // Keep room for the null terminator
+    field.resize(len, 0);
+    packet.extend_from_slice(&field);
+}
+
+pub fn build_art_poll_reply(ip: Ipv4Addr, universe: u16) -> Vec<u8> {
+    let mut packet = header(OP_POLL_REPLY);
+    packet.extend_from_slice(&ip.octets());
+    packet.extend_from_slice(&ARTNET_PORT.to_le_bytes());
+    packet.extend_from_slice(&[0, 1]);                      // Firmware version
+    packet.push(((universe >> 8) & 0x7f) as u8);            // NetSwitch
+    packet.push(((universe >> 4) & 0x0f) as u8);            // SubSwitch
+    packet.extend_from_slice(&[0xff, 0xff]);                // OEM unknown
+    packet.push(0);                                         // UBEA version
+    packet.push(0xd0);                                      // Status1: indicators normal, network configured
+    packet.extend_from_slice(&[0, 0]);                      // ESTA manufacturer
+    push_str(&mut packet, SHORT_NAME, 18);
+    push_str(&mut packet, LONG_NAME, 64);
+    push_str(&mut packet, "#0001 [0000] Power On Tests successful", 64);
+    packet.extend_from_slice(&1u16.to_be_bytes());          // NumPorts
+    packet.extend_from_slice(&[0x40, 0, 0, 0]);             // PortTypes: DMX512 input to Art-Net
+    packet.extend_from_slice(&[0x80, 0, 0, 0]);             // GoodInput: data received
+    packet.extend_from_slice(&[0, 0, 0, 0]);                // GoodOutput
+    packet.extend_from_slice(&[(universe & 0x0f) as u8, 0, 0, 0]);  // SwIn
+    packet.extend_from_slice(&[0, 0, 0, 0]);                // SwOut
+    packet.extend_from_slice(&[0, 0, 0]);                   // SwVideo, SwMacro, SwRemote
+    packet.extend_from_slice(&[0, 0, 0]);                   // Spare
+    packet.push(0x01);                                      // Style: StController
+    packet.extend_from_slice(&[0; 6]);                      // MAC
+    packet.extend_from_slice(&ip.octets());                 // BindIp
+    packet.push(1);                                         // BindIndex
+    packet.push(0x08);                                      // Status2: 15-bit port-address
+    packet.resize(239, 0);                                  // Filler
+    packet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn listener() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        socket
+    }
+
+    fn build_art_poll() -> Vec<u8> {
+        let mut packet = header(OP_POLL);
+        packet.extend_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+        packet.extend_from_slice(&[0, 0]);     // Flags, diagnostics priority
+        packet
+    }
+
+    #[test]
+    fn test_art_dmx_packet_layout() {
+        let packet = build_art_dmx(0x123, 7, &[1, 2, 3]);
+        assert_eq!(b"Art-Net\0", &packet[..8]);
+        assert_eq!(Some(OP_DMX), opcode(&packet));
+        assert_eq!([0, 14, 7, 0, 0x23, 0x01, 0, 4], packet[10..18]);
+        assert_eq!([1, 2, 3, 0], packet[18..]);
+    }
+
+    #[test]
+    fn test_parse_art_dmx() {
+        let packet = build_art_dmx(0x123, 7, &[1, 2, 3]);
+        assert_eq!(Some((0x123, &[1, 2, 3, 0][..])), parse_art_dmx(&packet));
+        assert_eq!(None, parse_art_dmx(&packet[..20]));
+        assert_eq!(None, parse_art_dmx(&build_art_poll()));
+    }
+
+    #[test]
+    fn test_receiver_maps_universes() {
+        let mut receiver = ArtNetReceiver::new("127.0.0.1:0", 4, 2).unwrap();
+        let addr = receiver.socket.local_addr().unwrap();
+        let console = listener();
+
+        console.send_to(&build_art_dmx(3, 1, &[9, 9]), addr).unwrap();
+        console.send_to(&build_art_dmx(5, 1, &[1, 2]), addr).unwrap();
+
+        assert_eq!(None, receiver.receive().unwrap());
+        assert_eq!(Some((2, vec![1, 2])), receiver.receive().unwrap());
+    }
+
+    #[test]
+    fn test_send_to_loopback_listener() {
+        let listener = listener();
+        let target = listener.local_addr().unwrap().to_string();
+        let mut node = ArtNetNode::new("127.0.0.1:0", &target, 1).unwrap();
+
+        node.send_universe(1, &[10, 20, 30, 40]).unwrap();
+        node.send_universe(1, &[50, 60]).unwrap();
+
+        let mut buf = [0; 1024];
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(22, len);
+        assert_eq!(1, buf[12]);
+        assert_eq!([10, 20, 30, 40], buf[18..22]);
+
+        let len = listener.recv(&mut buf).unwrap();
+        assert_eq!(20, len);
+        assert_eq!(2, buf[12]);
+        assert_eq!([50, 60], buf[18..20]);
+    }
+
+    #[test]
+    fn test_port_addresses_past_the_last_are_left_out() {
+        let listener = listener();
+        let target = listener.local_addr().unwrap().to_string();
+        let mut node = ArtNetNode::new("127.0.0.1:0", &target, MAX_PORT_ADDRESS).unwrap();
+
+        let mut universes = Universes::new();
+        universes.insert(1, vec![7; 4]);
+        universes.insert(2, vec![9; 4]);
+        universes.insert(u16::MAX, vec![9; 4]);
+        node.send(&universes).unwrap();
+
+        let mut buf = [0; 1024];
+        listener.recv(&mut buf).unwrap();
+        assert_eq!(Some((MAX_PORT_ADDRESS, &[7, 7, 7, 7][..])), parse_art_dmx(&buf[..22]));
+        listener.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        assert!(listener.recv(&mut buf).is_err());
+    }
+
+    #[test]
+    fn test_replies_to_art_poll() {
+        let controller = listener();
+        let target = controller.local_addr().unwrap().to_string();
+        let mut node = ArtNetNode::new("127.0.0.1:0", &target, 0x21).unwrap();
+
+        controller.send_to(&build_art_poll(), node.socket.local_addr().unwrap()).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        node.poll().unwrap();
+
+        let mut buf = [0; 1024];
+        let len = controller.recv(&mut buf).unwrap();
+        assert_eq!(239, len);
+        assert_eq!(Some(OP_POLL_REPLY), opcode(&buf[..len]));
+        assert_eq!([127, 0, 0, 1], buf[10..14]);
+        assert_eq!(b"Lightboard\0", &buf[26..37]);
+        assert_eq!(0x02, buf[19]);
+        assert_eq!(0x01, buf[200]);
+    }
+}
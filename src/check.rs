@@ -0,0 +1,142 @@
+use std::fmt;
+use std::fs::read_to_string;
+use serde::de::DeserializeOwned;
+use crate::show_loader;
+
+/// A problem found in a show, installation or fixture file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(file: &str, line: Option<usize>, message: &str) -> Self {
+        Self { file: file.to_owned(), line, message: message.to_owned() }
+    }
+
+    /// A problem at a byte offset into the file's text, such as a span
+    /// from the TOML parser.
+    pub fn at(file: &str, text: &str, offset: usize, message: &str) -> Self {
+        Self::new(file, Some(line_of(text, offset)), message)
+    }
+
+    /// A problem with a table such as `[outputs.dongle]`, at its header if
+    /// it has one.
+    pub fn in_table(file: &str, text: &str, table: &str, message: &str) -> Self {
+        let line = text.find(&format!("[{}]", table)).map(|offset| line_of(text, offset));
+        Self::new(file, line, message)
+    }
+
+    pub fn from_toml(file: &str, error: &toml::de::Error) -> Self {
+        // The parser's lines are 0-based, editors' are 1-based
+        let line = error.line_col().map(|(line, _)| line + 1);
+        Self::new(file, line, &format!("Failed to parse: {}", error))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.file, line, self.message),
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+/// The 1-based line containing a byte offset into some text.
+pub fn line_of(text: &str, offset: usize) -> usize {
+    text.as_bytes().iter().take(offset).filter(|c| **c == b'\n').count() + 1
+}
+
+/// The byte offset of the first quoted occurrence of a string from an offset
+/// on, for values that aren't deserialized with their spans.
+pub fn find(text: &str, value: &str, from: usize) -> Option<usize> {
+    let from = from.min(text.len());
+    text[from..].find(&format!("\"{}\"", value)).map(|offset| from + offset)
+}
+
+/// Reads and parses a configuration file, returning its text too so problems
+/// can be found in it.
+pub fn read_toml<T: DeserializeOwned>(file: &str) -> Result<(String, T), Diagnostic> {
+    let text = read_to_string(file)
+        .map_err(|e| Diagnostic::new(file, None, &format!("Failed to read: {}", e)))?;
+    let config = toml::from_str(&text).map_err(|e| Diagnostic::from_toml(file, &e))?;
+    Ok((text, config))
+}
+
+/// The first of some problems as an error, for loaders that stop at one.
+pub fn first_problem(diagnostics: Vec<Diagnostic>) -> Result<(), Diagnostic> {
+    match diagnostics.into_iter().next() {
+        Some(diagnostic) => Err(diagnostic),
+        None => Ok(()),
+    }
+}
+
+/// Checks a show together with its installation and the fixture definitions
+/// it uses, without loading any of them.
+pub fn check(show_file: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    show_loader::check_config(show_file, &mut diagnostics);
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic_lines() {
+        let text = "a = 1\nb = \"x\"\n\nc = 3\n";
+        assert_eq!(1, line_of(text, 0));
+        assert_eq!(2, line_of(text, 6));
+        assert_eq!(4, line_of(text, text.find('c').unwrap()));
+        assert_eq!(Some(10), find(text, "x", 0));
+        assert_eq!(None, find(text, "x", 11));
+        assert_eq!(None, find(text, "y", 0));
+
+        let error = toml::from_str::<toml::Value>("a = 1\nb = \n").unwrap_err();
+        let diagnostic = Diagnostic::from_toml("show.toml", &error);
+        assert_eq!(Some(2), diagnostic.line);
+        assert!(diagnostic.to_string().starts_with("show.toml:2: Failed to parse"));
+
+        let text = "[fixtures.1]\nkind = \"par\"\n\n[outputs.dongle]\nkind = \"serial\"\n";
+        assert_eq!(Some(4), Diagnostic::in_table("i.toml", text, "outputs.dongle", "x").line);
+        assert_eq!(None, Diagnostic::in_table("i.toml", text, "outputs.node", "x").line);
+    }
+
+    #[test]
+    fn test_wheel_slot_names() {
+        let dir = std::env::temp_dir().join("lightboard_slot_names");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("installation.toml"),
+                       "[fixtures.spot]\nkind = \"generic_spot\"\naddress = \"1.1\"\nmode = \"standard\"\npos = [0, 0]\n")
+            .unwrap();
+
+        let show = format!(r#"groups = {{}}
+pool = {{}}
+cues = []
+
+[library]
+fixtures = ["{}/fixtures"]
+
+[[effects]]
+name = "Gobos"
+elements = [
+    {{target = "spot:gobo:slot", value = "star"}},
+    {{target = "spot:gobo:slot", value = "stars"}},
+]
+"#, env!("CARGO_MANIFEST_DIR"));
+        let show_file = dir.join("show.toml");
+        std::fs::write(&show_file, show).unwrap();
+
+        let messages: Vec<_> = check(show_file.to_str().unwrap()).into_iter().map(|d| d.message).collect();
+        assert_eq!(vec!["Effect Gobos: target spot:gobo:slot has no slot named stars"], messages);
+    }
+
+    #[test]
+    fn test_included_show_is_valid() {
+        assert_eq!(Vec::<Diagnostic>::new(), check("show.toml"));
+    }
+}
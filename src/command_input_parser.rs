@@ -1,15 +1,21 @@
-use nom::character::complete::{digit1, one_of};
-use nom::sequence::pair;
+use nom::character::complete::{digit1, hex_digit1, one_of};
+use nom::sequence::{pair, preceded, separated_pair};
 use nom::branch::alt;
-use nom::bytes::complete::tag;
+use nom::bytes::complete::{tag, take_till1};
+use nom::combinator::{map, map_res};
 use nom::multi::separated_list;
 use nom::IResult;
 use crate::effect::{Command, Action};
+use crate::park::ParkTarget;
 
 #[derive(Debug, PartialEq)]
 pub enum Chunk {
     Effect(Command),
     CueNum(usize),
+    /// `park par:1 255` or `park 2.301 0x80`
+    Park(ParkTarget, i32),
+    /// `unpark par:1`, or `unpark all` for every park
+    Unpark(Option<ParkTarget>),
 }
 
 fn alpha_any_case(i: &str) -> IResult<&str, char> {
@@ -36,8 +42,33 @@ fn cue_num(i: &str) -> IResult<&str, Chunk> {
     }
 }
 
+fn park_target(i: &str) -> IResult<&str, ParkTarget> {
+    map_res(take_till1(|c| c == ' '), |s: &str| s.parse::<ParkTarget>())(i)
+}
+
+fn park_value(i: &str) -> IResult<&str, i32> {
+    alt((
+        map_res(preceded(tag("0x"), hex_digit1), |s| i32::from_str_radix(s, 16)),
+        map_res(digit1, |s: &str| s.parse::<i32>()),
+    ))(i)
+}
+
+fn park(i: &str) -> IResult<&str, Chunk> {
+    map(
+        preceded(tag("park "), separated_pair(park_target, tag(" "), park_value)),
+        |(target, value)| Chunk::Park(target, value),
+    )(i)
+}
+
+fn unpark(i: &str) -> IResult<&str, Chunk> {
+    preceded(tag("unpark "), alt((
+        map(tag("all"), |_| Chunk::Unpark(None)),
+        map(park_target, |target| Chunk::Unpark(Some(target))),
+    )))(i)
+}
+
 fn chunk(i: &str) -> IResult<&str, Chunk> {
-    alt((effect_keymap, cue_num))(i)
+    alt((park, unpark, effect_keymap, cue_num))(i)
 }
 
 fn chunks(i: &str) -> IResult<&str, Vec<Chunk>> {
@@ -74,4 +105,14 @@ mod tests {
             })
         ], parse("a1 102 E52"));
     }
+
+    #[test]
+    fn test_parse_park() {
+        assert_eq!(vec![
+            Chunk::Park("par:1".parse().unwrap(), 0x4300ff),
+            Chunk::Park("2.301".parse().unwrap(), 255),
+            Chunk::Unpark(Some("1.5".parse().unwrap())),
+            Chunk::Unpark(None),
+        ], parse("park par:1 0x4300ff park 2.301 255 unpark 1.5 unpark all"));
+    }
 }
@@ -1,5 +1,6 @@
 use std::result::Result;
 use crate::show_loader::build_cue_list_from_config;
+use crate::check::Diagnostic;
 
 pub struct Cue {
     name: String,
@@ -15,7 +16,7 @@ impl CueList {
         CueList { cues: vec![] }
     }
 
-    pub fn new_from_config(config_file: &str) -> CueList {
+    pub fn new_from_config(config_file: &str) -> Result<CueList, Diagnostic> {
         build_cue_list_from_config(config_file)
     }
 
@@ -1,24 +1,370 @@
-use std::sync::mpsc;
-use std::{thread, time};
-use dmx::{self, DmxTransmitter};
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::output::{DmxOutput, OutputConfig};
+use crate::patch::{Address, Universes};
 
-pub fn update(recv: mpsc::Receiver<Vec<u8>>) {
-    let mut dmx_port = match dmx::open_serial("/dev/ttyUSB0") {
-        Ok(port) => port,
-        Err(_) => {
-            println!("Unable to connect to serial port!");
-            return
-        },
-    };
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+/// How long without a new frame before the show is taken to have stopped
+const STALL_TIMEOUT: Duration = Duration::from_secs(1);
 
-    let mut data = vec![];
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputState {
+    Connecting,
+    Connected,
+    /// The output couldn't be opened and will be retried
+    Disconnected(String),
+    /// Sending failed, so the output was closed and will be reopened
+    Error(String),
+    /// Sending failed and the output can't be reopened without losing what
+    /// it has already sent, as with recordings
+    Stopped(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FrameStats {
+    /// Frames sent per second over the last status interval
+    pub frame_rate: f32,
+    pub frames_sent: u64,
+    /// Unchanged frames not sent because of `send_on_change`
+    pub frames_skipped: u64,
+    /// Frames that started after their deadline
+    pub late_frames: u64,
+    /// Longest send over the last status interval
+    pub max_send_time: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputStatus {
+    pub name: String,
+    pub state: OutputState,
+    pub stats: FrameStats,
+}
+
+type SharedFrame = Arc<Mutex<Universes>>;
+
+struct OutputSlot {
+    config: OutputConfig,
+    output: Option<Box<dyn DmxOutput>>,
+    state: OutputState,
+    last_attempt: Option<Instant>,
+    stats: FrameStats,
+    interval_frames: u32,
+    last_sent: Option<(Universes, Instant)>,
+}
+
+impl OutputSlot {
+    fn new(config: OutputConfig) -> Self {
+        Self {
+            config,
+            output: None,
+            state: OutputState::Connecting,
+            last_attempt: None,
+            stats: FrameStats::default(),
+            interval_frames: 0,
+            last_sent: None,
+        }
+    }
+
+    fn connect(&mut self) {
+        if self.output.is_some() || matches!(self.state, OutputState::Stopped(_)) {
+            return;
+        }
+
+        if let Some(last_attempt) = self.last_attempt {
+            if last_attempt.elapsed() < RETRY_INTERVAL {
+                return;
+            }
+        }
+
+        self.last_attempt = Some(Instant::now());
+
+        match self.config.open() {
+            Ok(output) => {
+                println!("Opened output {}", self.config.name);
+                self.output = Some(output);
+                self.state = OutputState::Connected;
+            },
+            Err(e) => {
+                // Only report the first failure, not every retry
+                if !matches!(self.state, OutputState::Disconnected(_)) {
+                    println!("Unable to open output {}: {}", self.config.name, e);
+                }
+
+                self.state = OutputState::Disconnected(e.to_string());
+            },
+        }
+    }
+
+    fn is_due(&self, data: &Universes) -> bool {
+        let timing = &self.config.timing;
+
+        match &self.last_sent {
+            Some((last_data, sent_at)) if timing.send_on_change => {
+                last_data != data || sent_at.elapsed() >= timing.keep_alive
+            },
+            _ => true,
+        }
+    }
+
+    fn send(&mut self, data: &Universes) {
+        if self.output.is_none() {
+            return;
+        }
+
+        if !self.is_due(data) {
+            self.stats.frames_skipped += 1;
+            return;
+        }
+
+        let start = Instant::now();
+        let result = self.output.as_mut().unwrap().send(data);
+
+        match result {
+            Ok(_) => {
+                self.stats.frames_sent += 1;
+                self.stats.max_send_time = self.stats.max_send_time.max(start.elapsed());
+                self.interval_frames += 1;
+                self.last_sent = Some((data.clone(), start));
+            },
+            Err(e) => {
+                println!("Output {} failed: {}", self.config.name, e);
+                self.output = None;
+                self.last_sent = None;
+                self.state = if self.config.kind.reopens() {
+                    OutputState::Error(e.to_string())
+                } else {
+                    OutputState::Stopped(e.to_string())
+                };
+            },
+        }
+    }
+
+    fn status(&mut self, elapsed: Duration) -> OutputStatus {
+        self.stats.frame_rate = self.interval_frames as f32 / elapsed.as_secs_f32();
+
+        let status = OutputStatus {
+            name: self.config.name.clone(),
+            state: self.state.clone(),
+            stats: self.stats.clone(),
+        };
+
+        self.interval_frames = 0;
+        self.stats.max_send_time = Duration::default();
+        status
+    }
+}
+
+// Send the shared frame until `stop` is set, then send it once more so the
+// frame it was set after goes out before returning
+fn run_output(config: OutputConfig, frame: SharedFrame, stop: Arc<AtomicBool>,
+              status_send: mpsc::Sender<OutputStatus>)
+{
+    let period = config.timing.period();
+    let mut slot = OutputSlot::new(config);
+    let mut next_frame = Instant::now();
+    let mut last_status = Instant::now();
 
     loop {
-        if let Ok(new_data) = recv.try_recv() {
-            data = new_data;
+        slot.connect();
+        let stopping = stop.load(Ordering::SeqCst);
+        let data = frame.lock().unwrap().clone();
+        slot.send(&data);
+
+        if stopping {
+            return;
         }
 
-        dmx_port.send_dmx_packet(&data).unwrap();
-        thread::sleep(time::Duration::new(0, 20_000_000));
+        let elapsed = last_status.elapsed();
+
+        if elapsed >= STATUS_INTERVAL {
+            last_status = Instant::now();
+            // Nobody may be listening once the GUI has exited
+            let _ = status_send.send(slot.status(elapsed));
+        }
+
+        // Schedule from the previous deadline rather than from now, so time
+        // spent sending doesn't make the rate drift. Start over if we've
+        // fallen more than a whole frame behind.
+        next_frame += period;
+        let now = Instant::now();
+
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            slot.stats.late_frames += 1;
+
+            if now - next_frame > period {
+                next_frame = now;
+            }
+        }
+    }
+}
+
+// Zero the given addresses in a frame
+fn cut(data: &mut Universes, addresses: &[Address]) {
+    for address in addresses {
+        if let Some(slot) = data.get_mut(&address.universe).and_then(|chain| chain.get_mut(address.index())) {
+            *slot = 0;
+        }
+    }
+}
+
+/// Start a thread for each output, paced by its own timing, and feed them
+/// the latest frame until the sender is dropped. If frames stop arriving the
+/// `stall_cut` addresses are zeroed, so nothing like a smoke machine is left
+/// running when the show hangs or exits. Once the sender is dropped this
+/// returns after every output has sent the cut frame.
+pub fn update(recv: mpsc::Receiver<Universes>, status_send: mpsc::Sender<OutputStatus>,
+              configs: Vec<OutputConfig>, stall_cut: Vec<Address>)
+{
+    if configs.is_empty() {
+        println!("No DMX outputs configured!");
+        return
+    }
+
+    let frame = Arc::new(Mutex::new(Universes::new()));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    let threads: Vec<_> = configs.into_iter().map(|config| {
+        let frame = frame.clone();
+        let stop = stop.clone();
+        let status_send = status_send.clone();
+        thread::spawn(move || run_output(config, frame, stop, status_send))
+    }).collect();
+
+    let mut stalled = false;
+
+    loop {
+        match recv.recv_timeout(STALL_TIMEOUT) {
+            Ok(data) => {
+                *frame.lock().unwrap() = data;
+                stalled = false;
+            },
+            Err(RecvTimeoutError::Timeout) => {
+                if !stalled && !stall_cut.is_empty() {
+                    println!("No frames for {:?}, cutting smoke", STALL_TIMEOUT);
+                }
+
+                stalled = true;
+                cut(&mut frame.lock().unwrap(), &stall_cut);
+            },
+            Err(RecvTimeoutError::Disconnected) => {
+                cut(&mut frame.lock().unwrap(), &stall_cut);
+                stop.store(true, Ordering::SeqCst);
+
+                for thread in threads {
+                    let _ = thread.join();
+                }
+
+                return;
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::OutputKind;
+    use crate::recording::Recording;
+
+    #[test]
+    fn test_unavailable_output_is_disconnected() {
+        let config = OutputConfig::new("capture", OutputKind::File("/nonexistent/capture.txt".to_owned()));
+        let mut slot = OutputSlot::new(config);
+        slot.connect();
+        slot.send(&Universes::new());
+
+        let status = slot.status(Duration::from_secs(1));
+        assert!(matches!(status.state, OutputState::Disconnected(_)));
+        assert_eq!(0, status.stats.frames_sent);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_failed_recording_isnt_reopened() {
+        let config = OutputConfig::new("capture", OutputKind::File("/dev/full".to_owned()));
+        let mut slot = OutputSlot::new(config);
+        slot.connect();
+        slot.send(&Universes::new());
+        assert!(matches!(slot.state, OutputState::Stopped(_)));
+
+        slot.last_attempt = None;
+        slot.connect();
+        assert!(slot.output.is_none());
+    }
+
+    #[test]
+    fn test_connected_output_counts_frames() {
+        let mut slot = OutputSlot::new(OutputConfig::new("null", OutputKind::Null));
+        slot.connect();
+
+        for _ in 0..10 {
+            slot.send(&Universes::new());
+        }
+
+        let status = slot.status(Duration::from_millis(500));
+        assert_eq!(OutputState::Connected, status.state);
+        assert_eq!(20.0, status.stats.frame_rate);
+        assert_eq!(10, status.stats.frames_sent);
+    }
+
+    #[test]
+    fn test_cut_zeroes_addresses() {
+        let mut data = Universes::new();
+        data.insert(1, vec![255; 512]);
+        cut(&mut data, &[Address::new(1, 3), Address::new(2, 1)]);
+
+        assert_eq!([255, 255, 0, 255], data[&1][..4]);
+        assert!(!data.contains_key(&2));
+    }
+
+    #[test]
+    fn test_cut_is_sent_before_returning() {
+        let path = std::env::temp_dir().join("lightboard_stall_cut.lbrec");
+        let path = path.to_str().unwrap();
+        let config = OutputConfig::new("capture", OutputKind::File(path.to_owned()));
+
+        let (send, recv) = mpsc::channel();
+        let (status_send, _status_recv) = mpsc::channel();
+        let mut data = Universes::new();
+        data.insert(1, vec![255; 512]);
+        send.send(data).unwrap();
+        drop(send);
+
+        update(recv, status_send, vec![config], vec![Address::new(1, 1)]);
+
+        let recording = Recording::load(path).unwrap();
+        let (_, last) = recording.frames().last().unwrap();
+        assert_eq!([0, 255], last[&1][..2]);
+    }
+
+    #[test]
+    fn test_send_on_change_skips_unchanged_frames() {
+        let mut config = OutputConfig::new("null", OutputKind::Null);
+        config.timing.send_on_change = true;
+        config.timing.keep_alive = Duration::from_millis(50);
+
+        let mut slot = OutputSlot::new(config);
+        slot.connect();
+
+        let mut data = Universes::new();
+        data.insert(1, vec![0; 512]);
+        slot.send(&data);
+        slot.send(&data);
+        assert_eq!(1, slot.stats.frames_sent);
+        assert_eq!(1, slot.stats.frames_skipped);
+
+        data.get_mut(&1).unwrap()[0] = 255;
+        slot.send(&data);
+        assert_eq!(2, slot.stats.frames_sent);
+
+        thread::sleep(Duration::from_millis(60));
+        slot.send(&data);
+        assert_eq!(3, slot.stats.frames_sent);
     }
 }
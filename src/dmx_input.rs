@@ -0,0 +1,111 @@
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use crate::artnet::ArtNetReceiver;
+use crate::sacn::SacnReceiver;
+use crate::installation_loader;
+use crate::check::Diagnostic;
+
+/// How long a receive waits for a packet before giving up.
+pub const RECEIVE_TIMEOUT: Duration = Duration::from_millis(250);
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Something DMX can be received from, such as another console.
+pub trait DmxInput {
+    /// Wait up to `RECEIVE_TIMEOUT` for one universe of data, returned as
+    /// `(patch universe, data)`.
+    fn receive(&mut self) -> io::Result<Option<(u16, Vec<u8>)>>;
+}
+
+#[derive(Debug, Clone)]
+pub enum InputKind {
+    /// `universe` is the port-address received as patch universe 1
+    ArtNet {
+        bind: String,
+        universe: u16,
+        count: u16,
+    },
+    /// `universe` is the sACN universe received as patch universe 1
+    Sacn {
+        bind: String,
+        universe: u16,
+        count: u16,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct InputConfig {
+    pub name: String,
+    pub kind: InputKind,
+}
+
+impl InputConfig {
+    pub fn new(name: &str, kind: InputKind) -> Self {
+        Self { name: name.to_owned(), kind }
+    }
+
+    pub fn new_from_config(config_file: &str) -> Result<Vec<InputConfig>, Diagnostic> {
+        installation_loader::build_inputs_from_config(config_file)
+    }
+
+    pub fn open(&self) -> io::Result<Box<dyn DmxInput>> {
+        let input: Box<dyn DmxInput> = match &self.kind {
+            InputKind::ArtNet{bind, universe, count} => {
+                Box::new(ArtNetReceiver::new(bind, *universe, *count)?)
+            },
+            InputKind::Sacn{bind, universe, count} => {
+                Box::new(SacnReceiver::new(bind, *universe, *count)?)
+            },
+        };
+
+        Ok(input)
+    }
+}
+
+/// One universe received from the input at `input` in the list of configs.
+#[derive(Debug, Clone)]
+pub struct InputFrame {
+    pub input: usize,
+    pub universe: u16,
+    pub data: Vec<u8>,
+}
+
+/// Receive from each input on its own thread, reopening inputs that fail.
+pub fn listen(configs: Vec<InputConfig>, send: mpsc::Sender<InputFrame>) {
+    for (i, config) in configs.into_iter().enumerate() {
+        let send = send.clone();
+        thread::spawn(move || run_input(i, config, send));
+    }
+}
+
+fn run_input(index: usize, config: InputConfig, send: mpsc::Sender<InputFrame>) {
+    let mut last_error = None;
+
+    loop {
+        let error = match config.open() {
+            Ok(mut input) => loop {
+                match input.receive() {
+                    Ok(Some((universe, data))) => {
+                        let frame = InputFrame { input: index, universe, data };
+
+                        if send.send(frame).is_err() {
+                            return;
+                        }
+                    },
+                    Ok(None) => {},
+                    Err(e) => break e.to_string(),
+                }
+            },
+            Err(e) => e.to_string(),
+        };
+
+        // Only report a problem once, rather than on every retry
+        if last_error.as_ref() != Some(&error) {
+            println!("Input {}: {}, retrying", config.name, error);
+            last_error = Some(error);
+        }
+
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
@@ -1,10 +1,13 @@
 use std::collections::HashMap;
 use toml::value::Value;
 use crate::installation::Installation;
-use crate::pattern::Pattern;
-use crate::light::Color;
+use crate::pattern::{Pattern, ElementPosition};
+use crate::pixel_map::PixelMap;
+use crate::light::{Color, Intensity};
 use crate::fixture::{Element, ElementKind};
 use crate::show_loader;
+use crate::check::Diagnostic;
+use crate::library::Library;
 
 pub type GroupMap = HashMap<String, Vec<GroupElement>>;
 
@@ -13,6 +16,7 @@ pub struct EffectPool {
     effects: Vec<Effect>,
     groups: GroupMap,
     installation: String,
+    library: Library,
     key_map: HashMap<String, String>,
     command_queue: Vec<Command>
 }
@@ -40,6 +44,7 @@ pub struct Effect {
     strength: f32,
     effect_elements: Vec<EffectElement>,
     effect_patterns: Vec<Pattern>,
+    pixel_maps: Vec<PixelMap>,
 }
 
 #[derive(Debug)]
@@ -62,11 +67,12 @@ impl EffectElement {
 }
 
 impl EffectPool {
-    pub fn new(effects: Vec<Effect>, groups: GroupMap, installation: String) -> Self {
+    pub fn new(effects: Vec<Effect>, groups: GroupMap, installation: String, library: Library) -> Self {
         Self {
             effects,
             groups,
             installation,
+            library,
             key_map: HashMap::new(),
             command_queue: vec![],
         }
@@ -81,7 +87,7 @@ impl EffectPool {
         self.effects.iter_mut().find(|effect| &effect.name == name)
     }
 
-    pub fn new_from_config(config_file: &str) -> Self {
+    pub fn new_from_config(config_file: &str) -> Result<Self, Diagnostic> {
         show_loader::build_from_config(config_file)
     }
 
@@ -97,6 +103,11 @@ impl EffectPool {
         &self.installation
     }
 
+    /// Where the show's fixture definitions and patterns are found
+    pub fn library(&self) -> &Library {
+        &self.library
+    }
+
     pub fn add_commands(&mut self, mut commands: Vec<Command>) {
         self.command_queue.append(&mut commands);
     }
@@ -128,6 +139,30 @@ impl EffectPool {
             effect.reload_patterns();
         }
     }
+
+    /// Tell each pattern and pixel map where its group's elements are in the
+    /// installation.
+    pub fn locate(&mut self, installation: &Installation) {
+        let groups = &self.groups;
+        let positions = |group: &str| -> Vec<ElementPosition> {
+            groups.get(group).iter().flat_map(|elements| {
+                elements.iter().map(|group_element| {
+                    installation.element_position(&group_element.fixture, &group_element.element)
+                        .unwrap_or_default()
+                })
+            }).collect()
+        };
+
+        for effect in self.effects.iter_mut() {
+            for pattern in effect.effect_patterns.iter_mut() {
+                pattern.set_positions(positions(pattern.group()));
+            }
+
+            for pixel_map in effect.pixel_maps.iter_mut() {
+                pixel_map.set_positions(&positions(pixel_map.group()));
+            }
+        }
+    }
 }
 
 impl Effect {
@@ -138,18 +173,18 @@ impl Effect {
             strength,
             effect_elements: elements,
             effect_patterns: patterns,
+            pixel_maps: vec![],
         }
     }
 
+    pub fn set_pixel_maps(&mut self, pixel_maps: Vec<PixelMap>) {
+        self.pixel_maps = pixel_maps;
+    }
+
     pub fn apply_to(&mut self, installation: &mut Installation, groups: &GroupMap) {
         let strength = self.strength;
 
         for effect_element in &self.effect_elements {
-            let new_value = match effect_element.value {
-                Value::Integer(value) => value as i32,
-                _ => continue,
-            };
-
             let (fixture, element) = (&effect_element.fixture, &effect_element.element);
             let element = installation.find_element(fixture, element);
             let element = match element {
@@ -157,7 +192,17 @@ impl Effect {
                 None => continue,
             };
 
-            let kind = mix_into_element_kind(element, new_value, strength);
+            // Wheel slots can be picked by name
+            let new_value = match &effect_element.value {
+                Value::Integer(value) => *value as i32,
+                Value::String(name) => match element.wheel().slot_index(name) {
+                    Some(index) => index as i32,
+                    None => continue,
+                },
+                _ => continue,
+            };
+
+            let kind = mix_into_element_kind(element, &effect_element.property, new_value, strength);
             if let Some(kind) = kind {
                 element.set_kind(kind);
             }
@@ -166,20 +211,13 @@ impl Effect {
         for pattern in self.effect_patterns.iter_mut() {
             let pattern_elements = groups.get(pattern.group()).unwrap();
             let new_values = pattern.update();
+            mix_into_group(installation, pattern_elements, pattern.property(), &new_values, strength);
+        }
 
-            for (effect_element, new_value) in pattern_elements.iter().zip(new_values.iter()) {
-                let (fixture, element) = (&effect_element.fixture, &effect_element.element);
-                let element = installation.find_element(fixture, element);
-                let element = match element {
-                    Some(e) => e,
-                    None => continue,
-                };
-
-                let kind = mix_into_element_kind(element, *new_value, strength);
-                if let Some(kind) = kind {
-                    element.set_kind(kind);
-                }
-            }
+        for pixel_map in self.pixel_maps.iter_mut() {
+            let map_elements = groups.get(pixel_map.group()).unwrap();
+            let new_values = pixel_map.update();
+            mix_into_group(installation, map_elements, pixel_map.property(), &new_values, strength);
         }
     }
 
@@ -206,22 +244,249 @@ impl Effect {
     }
 }
 
-fn mix_into_element_kind(element: &Element, new_value: i32, strength: f32) -> Option<ElementKind> {
+fn mix_color(color: &Color, new_value: i32, strength: f32) -> Color {
+    let mut effect_color: Color = new_value.into();
+    effect_color.scale(strength);
+    color.clone() + effect_color
+}
+
+// Dimmer levels crossfade like positions, from full if nothing has set them,
+// so fading a dimmer effect out ends where the element started
+fn mix_intensity(intensity: Option<Intensity>, new_value: i32, strength: f32) -> Intensity {
+    let from = intensity.unwrap_or(1.0);
+    from + ((new_value & 0xff) as f32 / 255.0 - from) * strength
+}
+
+// Mix a value from a pattern or pixel map into each element of its group
+fn mix_into_group(installation: &mut Installation, group_elements: &[GroupElement], property: &str,
+                  new_values: &[i32], strength: f32) {
+    for (group_element, new_value) in group_elements.iter().zip(new_values.iter()) {
+        let element = match installation.find_element(&group_element.fixture, &group_element.element) {
+            Some(e) => e,
+            None => continue,
+        };
+
+        if let Some(kind) = mix_into_element_kind(element, property, *new_value, strength) {
+            element.set_kind(kind);
+        }
+    }
+}
+
+fn mix_into_element_kind(element: &Element, property: &str, new_value: i32,
+                         strength: f32) -> Option<ElementKind>
+{
     match element.kind() {
         ElementKind::Intensity(intensity) => {
             let effect_intensity = (new_value as i32 & 0xff) as f32 / 255.0 * strength;
             Some(ElementKind::Intensity(intensity + effect_intensity))
         }
-        ElementKind::Rgbi(color) => {
-            let mut effect_color: Color = (new_value as i32).into();
-            effect_color.scale(strength);
-            Some(ElementKind::Rgbi(color.clone() + effect_color))
+        ElementKind::Smoke(level) => {
+            let effect_level = (new_value as i32 & 0xff) as f32 / 255.0 * strength;
+            Some(ElementKind::Smoke(level + effect_level))
+        }
+        // Colour elements take a dimmer level separately from their colour.
+        // It's left at full until an effect with some strength sets it.
+        ElementKind::Rgbi{rgb, i} => match property {
+            "dimmer" if strength > 0.0 => {
+                Some(ElementKind::Rgbi{rgb: rgb.clone(), i: Some(mix_intensity(*i, new_value, strength))})
+            },
+            "dimmer" => None,
+            _ => Some(ElementKind::Rgbi{rgb: mix_color(rgb, new_value, strength), i: *i}),
+        },
+        ElementKind::Rgbiu{rgb, i, uv} => match property {
+            "dimmer" if strength > 0.0 => {
+                let i = Some(mix_intensity(*i, new_value, strength));
+                Some(ElementKind::Rgbiu{rgb: rgb.clone(), i, uv: *uv})
+            },
+            "dimmer" => None,
+            _ => Some(ElementKind::Rgbiu{rgb: mix_color(rgb, new_value, strength), i: *i, uv: *uv}),
+        },
+        // Positions can't be added together, so crossfade towards the new
+        // value by the effect's strength
+        ElementKind::Position{pan, tilt} => {
+            let mix = |from: f32| from + (new_value as f32 - from) * strength;
+
+            match property {
+                "pan" => Some(ElementKind::Position{pan: mix(*pan), tilt: *tilt}),
+                "tilt" => Some(ElementKind::Position{pan: *pan, tilt: mix(*tilt)}),
+                _ => None,
+            }
         },
-        ElementKind::Rgbiu{rgb: color, uv} => {
-            let mut effect_color: Color = (new_value as i32).into();
-            effect_color.scale(strength);
-            Some(ElementKind::Rgbiu{rgb: color.clone() + effect_color, uv: *uv})
+        // Slots can't be mixed, so any effect with some strength selects one.
+        // Speeds are given in percent and crossfade like positions.
+        ElementKind::Wheel{slot, rotation, scroll} => {
+            let mix = |from: f32| from + (new_value as f32 / 100.0 - from) * strength;
+
+            match property {
+                "slot" if strength > 0.0 => {
+                    let slot = new_value.max(0) as usize;
+                    Some(ElementKind::Wheel{slot, rotation: *rotation, scroll: *scroll})
+                },
+                "rotation" => Some(ElementKind::Wheel{slot: *slot, rotation: mix(*rotation), scroll: *scroll}),
+                "scroll" => Some(ElementKind::Wheel{slot: *slot, rotation: *rotation, scroll: mix(*scroll)}),
+                _ => None,
+            }
+        },
+        ElementKind::Params(values) => {
+            let index = element.param_index(property)?;
+            let mut values = values.clone();
+            values[index] += (new_value as f32 - values[index]) * strength;
+            Some(ElementKind::Params(values))
         },
         _=> None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use crate::recording::{Recorder, Recording};
+    use crate::fixture::{Wheel, Param};
+    use crate::fixture::tests::single_element_fixture;
+
+    const GOLDEN_SHOW: &str = "tests/golden/show.toml";
+    const GOLDEN_RECORDING: &str = "tests/golden/show.lbrec";
+
+    fn toggle(key: &str) -> Command {
+        Command { key: key.to_owned(), action: Action::Toggle }
+    }
+
+    // Run with UPDATE_GOLDEN=1 to rewrite the recording after an intended change
+    #[test]
+    fn test_apply_to_matches_golden_recording() {
+        let mut pool = EffectPool::new_from_config(GOLDEN_SHOW).unwrap();
+        let mut installation = Installation::new_from_config(pool.installation(), pool.library()).unwrap();
+        pool.add_commands(vec![toggle("A1"), toggle("A2"), toggle("A3")]);
+
+        let mut recorder = Recorder::new(vec![]).unwrap();
+        let start = Instant::now();
+
+        for i in 0..60 {
+            pool.run_commands();
+            pool.apply_to(&mut installation);
+            let at = Duration::from_millis(i * 1000 / 30);
+            recorder.record(at, &installation.build_dmx_chain(start + at)).unwrap();
+        }
+
+        let recorded = recorder.into_inner();
+
+        if env::var("UPDATE_GOLDEN").is_ok() {
+            fs::write(GOLDEN_RECORDING, &recorded).unwrap();
+        }
+
+        let expected = Recording::load(GOLDEN_RECORDING).unwrap();
+        let actual = Recording::read(recorded.as_slice()).unwrap();
+
+        for (i, (expected, actual)) in expected.frames().iter().zip(actual.frames()).enumerate() {
+            assert_eq!(expected, actual, "Frame {} differs from {}", i, GOLDEN_RECORDING);
+        }
+
+        assert_eq!(expected.frames().len(), actual.frames().len());
+    }
+
+    #[test]
+    fn test_wheel_slot_by_name() {
+        let mut gobo = Element::new(ElementKind::Wheel{slot: 0, rotation: 0.0, scroll: 0.0});
+        gobo.set_wheel(Wheel {
+            slots: vec![("open".to_owned(), (0, 7)), ("star".to_owned(), (8, 15))],
+            ..Wheel::default()
+        });
+        let mut fixtures = HashMap::new();
+        fixtures.insert("spot".to_owned(), single_element_fixture(gobo, 2));
+        let mut installation = Installation::new(fixtures);
+
+        let elements = vec![
+            EffectElement::new("spot", "1", "slot", &Value::String("star".to_owned())),
+            EffectElement::new("spot", "1", "rotation", &Value::Integer(-50)),
+        ];
+        let mut effect = Effect::new("Stars", 1.0, elements, vec![]);
+        effect.apply_to(&mut installation, &GroupMap::new());
+
+        match installation.find_element("spot", "1").unwrap().kind() {
+            ElementKind::Wheel{slot, rotation, ..} => assert_eq!((1, -0.5), (*slot, *rotation)),
+            kind => panic!("Unexpected element kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_dimmer_on_color_element() {
+        let par = Element::new(ElementKind::Rgbi{rgb: Color::black(), i: None});
+        let mut fixtures = HashMap::new();
+        fixtures.insert("par".to_owned(), single_element_fixture(par, 3));
+        let mut installation = Installation::new(fixtures);
+
+        let mut color = Effect::new("Blue", 1.0, vec![
+            EffectElement::new("par", "1", "color", &Value::Integer(0x0000ff)),
+        ], vec![]);
+        let mut dimmer = Effect::new("Blackout", 0.0, vec![
+            EffectElement::new("par", "1", "dimmer", &Value::Integer(0)),
+        ], vec![]);
+
+        let mut apply = |dimmer: &mut Effect, strength: f32| {
+            installation.zero();
+            dimmer.set_strength(strength);
+            color.apply_to(&mut installation, &GroupMap::new());
+            dimmer.apply_to(&mut installation, &GroupMap::new());
+
+            match installation.find_element("par", "1").unwrap().kind() {
+                ElementKind::Rgbi{rgb, i} => (rgb.b(), *i),
+                kind => panic!("Unexpected element kind {:?}", kind),
+            }
+        };
+
+        // Fading the effect out ends at full without a jump
+        assert_eq!((1.0, Some(0.0)), apply(&mut dimmer, 1.0));
+        assert_eq!((1.0, Some(0.5)), apply(&mut dimmer, 0.5));
+        assert_eq!((1.0, Some(0.99)), apply(&mut dimmer, 0.01));
+        assert_eq!((1.0, None), apply(&mut dimmer, 0.0));
+    }
+
+    #[test]
+    fn test_params_by_name() {
+        let mut beam = Element::new(ElementKind::Params(vec![0.0, 100.0]));
+        beam.set_params(vec![
+            Param { name: "zoom".to_owned(), range: (0.0, 255.0), dmx: (0, 255), default: 0.0 },
+            Param { name: "focus".to_owned(), range: (0.0, 255.0), dmx: (0, 255), default: 100.0 },
+        ]);
+        let mut fixtures = HashMap::new();
+        fixtures.insert("spot".to_owned(), single_element_fixture(beam, 2));
+        let mut installation = Installation::new(fixtures);
+
+        let elements = vec![
+            EffectElement::new("spot", "1", "focus", &Value::Integer(200)),
+            EffectElement::new("spot", "1", "iris", &Value::Integer(200)),
+        ];
+        let mut effect = Effect::new("Focus", 0.5, elements, vec![]);
+        effect.apply_to(&mut installation, &GroupMap::new());
+
+        match installation.find_element("spot", "1").unwrap().kind() {
+            ElementKind::Params(values) => assert_eq!(&vec![0.0, 150.0], values),
+            kind => panic!("Unexpected element kind {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_position_effects_crossfade() {
+        let mut head = Element::new(ElementKind::Position{pan: 0.0, tilt: 0.0});
+        head.add_channel("pan", 1);
+        head.add_channel("tilt", 2);
+        let mut fixtures = HashMap::new();
+        fixtures.insert("head".to_owned(), single_element_fixture(head, 2));
+        let mut installation = Installation::new(fixtures);
+
+        let elements = vec![
+            EffectElement::new("head", "1", "pan", &Value::Integer(90)),
+            EffectElement::new("head", "1", "tilt", &Value::Integer(-60)),
+        ];
+        let mut effect = Effect::new("Position", 0.5, elements, vec![]);
+        effect.apply_to(&mut installation, &GroupMap::new());
+
+        match installation.find_element("head", "1").unwrap().kind() {
+            ElementKind::Position{pan, tilt} => assert_eq!((45.0, -30.0), (*pan, *tilt)),
+            kind => panic!("Unexpected element kind {:?}", kind),
+        }
+    }
+}
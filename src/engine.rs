@@ -0,0 +1,279 @@
+use std::sync::mpsc;
+use std::sync::mpsc::Receiver;
+use std::time::Instant;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use crate::installation::Installation;
+use crate::effect::EffectPool;
+use crate::cue::CueList;
+use crate::command_input_parser::{self, Chunk};
+use crate::dmx_control::{OutputStatus, OutputState};
+use crate::patch::Universes;
+use crate::dmx_input::InputFrame;
+use crate::merge::{Merger, MergeRules};
+
+/// Ticks per second. Patterns assume this rate when they advance.
+pub const TICK_RATE: u32 = 30;
+
+type NotifyRx = Receiver<Result<notify::event::Event, notify::Error>>;
+
+/// Runs the show: applies commands and effects to the installation each tick
+/// and sends the result to the output thread. Used by both the GUI and
+/// headless mode.
+pub struct Engine {
+    installation: Installation,
+    effect_pool: EffectPool,
+    cue_list: CueList,
+    dmx_send: mpsc::Sender<Universes>,
+    dmx_status_recv: mpsc::Receiver<OutputStatus>,
+    dmx_chain: Universes,
+    dmx_status: Vec<OutputStatus>,
+    watcher: Option<RecommendedWatcher>,
+    watcher_recv: Option<NotifyRx>,
+    merger: Merger,
+    input_recv: Option<mpsc::Receiver<InputFrame>>,
+}
+
+impl Engine {
+    pub fn new(installation: Installation, mut effect_pool: EffectPool, cue_list: CueList,
+               dmx_send: mpsc::Sender<Universes>,
+               dmx_status_recv: mpsc::Receiver<OutputStatus>) -> Self
+    {
+        effect_pool.locate(&installation);
+
+        Self {
+            installation,
+            effect_pool,
+            cue_list,
+            dmx_send,
+            dmx_status_recv,
+            dmx_chain: Universes::new(),
+            dmx_status: vec![],
+            watcher: None,
+            watcher_recv: None,
+            merger: Merger::new(MergeRules::default(), vec![]),
+            input_recv: None,
+        }
+    }
+
+    /// Reload patterns whenever a file in one of the show's pattern
+    /// directories changes.
+    pub fn watch_patterns(&mut self) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher: RecommendedWatcher = Watcher::new_immediate(move |res| tx.send(res).unwrap()).unwrap();
+
+        for dir in self.effect_pool.library().pattern_dirs().iter().filter(|dir| dir.is_dir()) {
+            watcher.watch(dir, RecursiveMode::Recursive).unwrap();
+        }
+
+        self.watcher = Some(watcher);
+        self.watcher_recv = Some(rx);
+    }
+
+    /// Merge DMX received from inputs into each frame before it's sent.
+    pub fn merge_inputs(&mut self, merger: Merger, input_recv: mpsc::Receiver<InputFrame>) {
+        self.merger = merger;
+        self.input_recv = Some(input_recv);
+    }
+
+    pub fn tick(&mut self) {
+        if let Some(rx) = &self.watcher_recv {
+            // Drain all events, then reload patterns, since many events can
+            // be received at once.
+            // todo Consider a debounce.
+            let mut changed = false;
+
+            while rx.try_recv().is_ok() {
+                changed = true;
+            }
+
+            if changed {
+                self.effect_pool.reload_patterns();
+            }
+        }
+
+        self.effect_pool.run_commands();
+        self.effect_pool.apply_to(&mut self.installation);
+
+        let now = Instant::now();
+
+        if let Some(input_recv) = &self.input_recv {
+            while let Ok(frame) = input_recv.try_recv() {
+                self.merger.receive(frame, now);
+            }
+        }
+
+        let chain = self.installation.build_dmx_chain(now);
+        let chain = self.merger.merge(&chain, now);
+        self.dmx_chain = chain.clone();
+
+        if self.dmx_send.send(chain).is_err() {
+            for status in self.dmx_status.iter_mut() {
+                status.state = OutputState::Error("Output thread stopped".to_owned());
+                status.stats.frame_rate = 0.0;
+            }
+        }
+
+        while let Ok(status) = self.dmx_status_recv.try_recv() {
+            match self.dmx_status.iter_mut().find(|s| s.name == status.name) {
+                Some(existing) => *existing = status,
+                None => {
+                    self.dmx_status.push(status);
+                    self.dmx_status.sort_by(|a, b| a.name.cmp(&b.name));
+                }
+            }
+        }
+    }
+
+    /// Queue the commands in a line such as "a1 b2 3", expanding cue numbers
+    /// into their commands. They take effect on the next tick.
+    pub fn run_command_line(&mut self, line: &str) {
+        let chunks = command_input_parser::parse(line);
+        let mut commands = vec![];
+
+        for chunk in expand_cues(chunks, &self.cue_list) {
+            match chunk {
+                Chunk::Effect(command) => commands.push(command),
+                Chunk::Park(target, value) => {
+                    if let Err(e) = self.installation.park(target, value) {
+                        println!("{}", e);
+                    }
+                },
+                Chunk::Unpark(Some(target)) => {
+                    if !self.installation.unpark(&target) {
+                        println!("{} isn't parked", target);
+                    }
+                },
+                Chunk::Unpark(None) => self.installation.unpark_all(),
+                Chunk::CueNum(_) => {},
+            }
+        }
+
+        self.effect_pool.add_commands(commands);
+    }
+
+    pub fn installation(&self) -> &Installation {
+        &self.installation
+    }
+
+    pub fn installation_mut(&mut self) -> &mut Installation {
+        &mut self.installation
+    }
+
+    pub fn effect_pool_mut(&mut self) -> &mut EffectPool {
+        &mut self.effect_pool
+    }
+
+    pub fn cue_list(&self) -> &CueList {
+        &self.cue_list
+    }
+
+    pub fn dmx_chain(&self) -> &Universes {
+        &self.dmx_chain
+    }
+
+    pub fn dmx_status(&self) -> &[OutputStatus] {
+        &self.dmx_status
+    }
+
+    pub fn merger(&self) -> &Merger {
+        &self.merger
+    }
+}
+
+/// Replace cue numbers with the chunks of their commands.
+pub fn expand_cues(chunks: Vec<Chunk>, cue_list: &CueList) -> Vec<Chunk> {
+    let mut commands = vec![];
+
+    for chunk in chunks {
+        match chunk {
+            Chunk::CueNum(i) => {
+                let command = match cue_list.cue_command(i.wrapping_sub(1)) {
+                    Ok(command) => command,
+                    Err(_) => {
+                        println!("No cue {}", i);
+                        continue;
+                    },
+                };
+
+                let chunks = command_input_parser::parse(&command);
+                let mut result = expand_cues(chunks, cue_list);
+                commands.append(&mut result);
+            },
+            chunk => commands.push(chunk),
+        }
+    }
+
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge::Source;
+
+    fn golden_engine() -> (Engine, mpsc::Receiver<Universes>) {
+        let effect_pool = EffectPool::new_from_config("tests/golden/show.toml").unwrap();
+        let cue_list = CueList::new_from_config("tests/golden/show.toml").unwrap();
+        let installation = Installation::new_from_config(effect_pool.installation(), effect_pool.library()).unwrap();
+        let (send, recv) = mpsc::channel();
+        let (_status_send, status_recv) = mpsc::channel();
+        (Engine::new(installation, effect_pool, cue_list, send, status_recv), recv)
+    }
+
+    #[test]
+    fn test_command_line_drives_output() {
+        let (mut engine, recv) = golden_engine();
+        engine.tick();
+        assert_eq!(0, recv.recv().unwrap()[&1][21]);
+
+        engine.run_command_line("a1");
+        engine.tick();
+        let chain = recv.recv().unwrap();
+        assert_eq!(255, chain[&1][20]);
+        assert_eq!(0x43, chain[&1][21]);
+        assert_eq!(&chain, engine.dmx_chain());
+    }
+
+    #[test]
+    fn test_inputs_are_merged() {
+        let (mut engine, recv) = golden_engine();
+        let (input_send, input_recv) = mpsc::channel();
+        engine.merge_inputs(Merger::new(MergeRules::default(), vec!["console".to_owned()]), input_recv);
+
+        let mut data = vec![0; 512];
+        data[21] = 200;
+        input_send.send(InputFrame { input: 0, universe: 1, data }).unwrap();
+        engine.run_command_line("a1");
+        engine.tick();
+
+        let chain = recv.recv().unwrap();
+        assert_eq!(255, chain[&1][20]);
+        assert_eq!(200, chain[&1][21]);
+        assert_eq!(Source::Input(0), engine.merger().owners()[&1][21]);
+    }
+
+    #[test]
+    fn test_park_from_command_line() {
+        let (mut engine, recv) = golden_engine();
+        engine.run_command_line("a1 park par:1 0x00ff00");
+        engine.tick();
+        assert_eq!([0, 255, 0], recv.recv().unwrap()[&1][21..24]);
+
+        engine.run_command_line("unpark all");
+        engine.tick();
+        assert_eq!(0x43, recv.recv().unwrap()[&1][21]);
+        assert!(engine.installation().parks().is_empty());
+    }
+
+    #[test]
+    fn test_cue_number_expands_to_commands() {
+        let (mut engine, _recv) = golden_engine();
+        engine.run_command_line("1 2");
+        engine.tick();
+
+        let strengths: Vec<f32> = ["A1", "A2", "A3"].iter().map(|key| {
+            engine.effect_pool_mut().get_effect_by_key(key).unwrap().strength()
+        }).collect();
+        assert_eq!(vec![1.0, 1.0, 1.0], strengths);
+    }
+}
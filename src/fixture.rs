@@ -1,29 +1,347 @@
 use std::collections::HashMap;
-use ggez::nalgebra::Point2;
-use crate::light::{Color, Intensity};
+use std::time::{Duration, Instant};
+use std::f32::consts::TAU;
+use ggez::nalgebra::{Point2, Vector2};
+use crate::light::{Color, DimmerCurve, Intensity};
+use crate::patch::Address;
 
 #[derive(Debug)]
 pub enum ElementKind {
     Intensity(Intensity),
-    Rgbiu{rgb: Color, uv: Intensity},
-    Rgbi(Color),
+    /// A colour with its own intensity, which is `None` (full) until an
+    /// effect sets it
+    Rgbiu{rgb: Color, i: Option<Intensity>, uv: Intensity},
+    Rgbi{rgb: Color, i: Option<Intensity>},
     Uv(Intensity),
-    Smoke,
-    Actuator,
-    Gobo,
+    /// The requested output, which `SmokeTiming` may hold back
+    Smoke(Intensity),
+    /// Pan and tilt in degrees from the centre of the head's range
+    Position{pan: f32, tilt: f32},
+    /// A gobo or colour wheel showing one of its slots. Rotation and scroll
+    /// are speeds from -1.0 (fastest anticlockwise) to 1.0 (fastest clockwise),
+    /// and any scroll overrides the slot.
+    Wheel{slot: usize, rotation: f32, scroll: f32},
+    /// Values of the parameters defined for the element, in the same order
+    Params(Vec<f32>),
     Unknown,
 }
 
+/// How far a moving head can pan and tilt, in degrees, and whether either
+/// axis moves the opposite way to increasing DMX values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionRange {
+    pub pan: f32,
+    pub tilt: f32,
+    pub invert_pan: bool,
+    pub invert_tilt: bool,
+}
+
+impl Default for PositionRange {
+    fn default() -> Self {
+        Self { pan: 540.0, tilt: 270.0, invert_pan: false, invert_tilt: false }
+    }
+}
+
+/// DMX values for a continuously variable speed. Each direction's range runs
+/// from slowest to fastest, and either end may be the higher value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpeedRange {
+    pub stop: u8,
+    pub cw: Option<(u8, u8)>,
+    pub ccw: Option<(u8, u8)>,
+}
+
+impl SpeedRange {
+    /// The DMX value for a speed, or `None` for a speed the fixture can't do.
+    pub fn dmx(&self, speed: f32) -> Option<u8> {
+        let range = if speed > 0.0 {
+            self.cw
+        } else if speed < 0.0 {
+            self.ccw
+        } else {
+            return Some(self.stop);
+        };
+
+        let (slowest, fastest) = range?;
+        let speed = speed.abs().min(1.0);
+        Some((slowest as f32 + (fastest as f32 - slowest as f32) * speed).round() as u8)
+    }
+}
+
+/// The named slots of a gobo or colour wheel, each a range of DMX values on
+/// the wheel channel, plus the ranges that rotate or scroll it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wheel {
+    pub slots: Vec<(String, (u8, u8))>,
+    pub scroll: SpeedRange,
+    pub rotation: SpeedRange,
+}
+
+impl Wheel {
+    pub fn slot_index(&self, name: &str) -> Option<usize> {
+        self.slots.iter().position(|(slot, _)| slot == name)
+    }
+
+    pub fn slot_name(&self, index: usize) -> Option<&str> {
+        self.slots.get(index).map(|(name, _)| name.as_str())
+    }
+}
+
+/// A parameter of a generic element, such as zoom or strobe, which is written
+/// to the channel of the same name. Values from `range.0` to `range.1` are
+/// spread across the DMX values `dmx.0` to `dmx.1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: String,
+    pub range: (f32, f32),
+    pub dmx: (u8, u8),
+    pub default: f32,
+}
+
+impl Param {
+    // How far along its range a value is, from 0.0 to 1.0
+    fn along(&self, value: f32) -> f32 {
+        let (min, max) = self.range;
+
+        if max == min {
+            0.0
+        } else {
+            ((value - min) / (max - min)).clamp(0.0, 1.0)
+        }
+    }
+}
+
+/// The green in an amber emitter, relative to its red
+const AMBER_GREEN: f32 = 0.75;
+
+/// Limits on how a smoke or haze machine may run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SmokeTiming {
+    /// Longest a burst may last before it's cut
+    pub max_burst: Option<Duration>,
+    /// Shortest time from the end of one burst to the start of the next
+    pub cooldown: Duration,
+}
+
+#[derive(Debug, Clone, Default)]
+struct SmokeState {
+    burst_start: Option<Instant>,
+    cool_until: Option<Instant>,
+    output: Intensity,
+}
+
 #[derive(Debug)]
 pub struct Element {
     kind: ElementKind,
     channels: HashMap<String, u8>,
-    pos: (usize, usize),
+    pos: (f32, f32),
+    offset: Option<(f32, f32)>,
+    position_range: PositionRange,
+    wheel: Wheel,
+    params: Vec<Param>,
+    smoke_timing: SmokeTiming,
+    smoke: SmokeState,
+    curve: DimmerCurve,
+}
+
+// Map degrees from the centre of a range to a fraction of the DMX range
+fn degrees_to_fraction(degrees: f32, range: f32, invert: bool) -> f32 {
+    let fraction = (degrees / range.max(1.0) + 0.5).clamp(0.0, 1.0);
+
+    if invert {
+        1.0 - fraction
+    } else {
+        fraction
+    }
 }
 
 impl Element {
     pub fn new(kind: ElementKind) -> Element {
-        Element { kind, channels: HashMap::new(), pos: (0, 0) }
+        Element {
+            kind,
+            channels: HashMap::new(),
+            pos: (0.0, 0.0),
+            offset: None,
+            position_range: PositionRange::default(),
+            wheel: Wheel::default(),
+            params: vec![],
+            smoke_timing: SmokeTiming::default(),
+            smoke: SmokeState::default(),
+            curve: DimmerCurve::default(),
+        }
+    }
+
+    pub fn set_curve(&mut self, curve: DimmerCurve) {
+        self.curve = curve;
+    }
+
+    pub fn set_smoke_timing(&mut self, timing: SmokeTiming) {
+        self.smoke_timing = timing;
+    }
+
+    /// The smoke output actually allowed, after burst and cool-down limits.
+    pub fn smoke_output(&self) -> Intensity {
+        self.smoke.output
+    }
+
+    // Work out how much of the requested smoke may be output at `now`. A
+    // burst that runs too long is cut, and no burst starts while cooling down.
+    fn update_smoke(&mut self, now: Instant) {
+        let level = match self.kind {
+            ElementKind::Smoke(level) => level.clamp(0.0, 1.0),
+            _ => return,
+        };
+
+        let timing = &self.smoke_timing;
+        let smoke = &mut self.smoke;
+        smoke.output = 0.0;
+
+        if matches!(smoke.cool_until, Some(cool_until) if now < cool_until) {
+            return;
+        }
+
+        if level <= 0.0 {
+            if smoke.burst_start.take().is_some() {
+                smoke.cool_until = Some(now + timing.cooldown);
+            }
+
+            return;
+        }
+
+        let burst_start = *smoke.burst_start.get_or_insert(now);
+
+        match timing.max_burst {
+            Some(max_burst) if now.duration_since(burst_start) >= max_burst => {
+                smoke.burst_start = None;
+                smoke.cool_until = Some(now + timing.cooldown);
+            },
+            _ => smoke.output = level,
+        }
+    }
+
+    pub fn set_position_range(&mut self, range: PositionRange) {
+        self.position_range = range;
+    }
+
+    pub fn wheel(&self) -> &Wheel {
+        &self.wheel
+    }
+
+    pub fn set_wheel(&mut self, wheel: Wheel) {
+        self.wheel = wheel;
+    }
+
+    pub fn params(&self) -> &[Param] {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: Vec<Param>) {
+        self.params = params;
+    }
+
+    pub fn param_index(&self, name: &str) -> Option<usize> {
+        self.params.iter().position(|param| param.name == name)
+    }
+
+    pub fn param_defaults(&self) -> Vec<f32> {
+        self.params.iter().map(|param| param.default).collect()
+    }
+
+    // Write a point along a range of DMX values. For a 16-bit parameter the
+    // range covers the coarse channel and the fine channel fills in between.
+    fn write_range(&self, dmx: &mut [u8], channel: &str, range: (u8, u8), along: f32) {
+        let coarse = match self.channels.get(channel) {
+            Some(coarse) => (coarse - 1) as usize,
+            None => return,
+        };
+
+        let (low, high) = (range.0 as f32, range.1 as f32);
+
+        match self.channels.get(&format!("{}_fine", channel)) {
+            Some(fine) => {
+                let value = ((low + (high - low) * along) * 257.0).round() as u16;
+                dmx[coarse] = (value >> 8) as u8;
+                dmx[(fine - 1) as usize] = (value & 0xff) as u8;
+            },
+            None => dmx[coarse] = (low + (high - low) * along).round() as u8,
+        }
+    }
+
+    // Convert a colour for the element's emitters. White and amber replace
+    // the red, green and blue they'd be mixed from, and cyan, magenta and
+    // yellow filters subtract from white light.
+    fn write_color(&self, dmx: &mut [u8], color: &Color) {
+        let curve = &self.curve;
+        let (mut r, mut g, mut b) = (curve.apply(color.r()), curve.apply(color.g()), curve.apply(color.b()));
+
+        if ["c", "m", "y"].iter().any(|channel| self.channels.contains_key(*channel)) {
+            self.write_fraction(dmx, "c", 1.0 - r);
+            self.write_fraction(dmx, "m", 1.0 - g);
+            self.write_fraction(dmx, "y", 1.0 - b);
+            return;
+        }
+
+        if self.channels.contains_key("w") {
+            let w = r.min(g).min(b);
+            r -= w;
+            g -= w;
+            b -= w;
+            self.write_fraction(dmx, "w", w);
+        }
+
+        if self.channels.contains_key("a") {
+            let a = r.min(g / AMBER_GREEN);
+            r -= a;
+            g -= a * AMBER_GREEN;
+            self.write_fraction(dmx, "a", a);
+        }
+
+        self.write_fraction(dmx, "r", r);
+        self.write_fraction(dmx, "g", g);
+        self.write_fraction(dmx, "b", b);
+    }
+
+    // Send intensity to the element's dimmer channel or, for fixtures
+    // without one, scale the colour by it
+    fn write_color_intensity(&self, dmx: &mut [u8], color: &Color, intensity: Option<Intensity>) {
+        let intensity = intensity.unwrap_or(1.0);
+
+        if self.channels.contains_key("i") {
+            self.write_fraction(dmx, "i", self.curve.apply(intensity));
+            self.write_color(dmx, color);
+        } else {
+            let mut color = color.clone();
+            color.scale(intensity);
+            self.write_color(dmx, &color);
+        }
+    }
+
+    fn write_value(&self, dmx: &mut [u8], channel: &str, value: u8) {
+        if let Some(channel) = self.channels.get(channel) {
+            dmx[(channel - 1) as usize] = value;
+        }
+    }
+
+    // Write a fraction of full scale to a parameter's channel. A 16-bit
+    // parameter also has a `<name>_fine` channel for the low byte.
+    fn write_fraction(&self, dmx: &mut [u8], channel: &str, fraction: f32) {
+        let coarse = match self.channels.get(channel) {
+            Some(coarse) => (coarse - 1) as usize,
+            None => return,
+        };
+
+        match self.channels.get(&format!("{}_fine", channel)) {
+            Some(fine) => {
+                let value = (fraction * 65535.0) as u16;
+                dmx[coarse] = (value >> 8) as u8;
+                dmx[(fine - 1) as usize] = (value & 0xff) as u8;
+            },
+            None => dmx[coarse] = (fraction * 255.0) as u8,
+        }
+    }
+
+    pub fn channel(&self, name: &str) -> Option<u8> {
+        self.channels.get(name).cloned()
     }
 
     pub fn add_channel(&mut self, name: &str, index: u8) {
@@ -38,56 +356,199 @@ impl Element {
         self.kind = kind;
     }
 
-    pub fn pos(&self) -> (usize, usize) {
+    /// The top left of the element's cell within its fixture, in cells
+    pub fn pos(&self) -> (f32, f32) {
         self.pos
     }
 
-    pub fn set_pos(&mut self, x: usize, y: usize) {
+    pub fn set_pos(&mut self, x: f32, y: f32) {
         self.pos = (x, y);
     }
 
-    pub fn arrange_linear(elements: &mut HashMap<String, Element>) {
-        let mut element_names: Vec<String> = elements.iter().map(|(name, _element)| name.clone()).collect();
-        element_names.sort();
-        for (i, name) in element_names.iter().enumerate() {
-            elements.get_mut(name).unwrap().set_pos(i, 0);
+    /// Place the element's cell here whatever the fixture's layout
+    pub fn set_offset(&mut self, x: f32, y: f32) {
+        self.offset = Some((x, y));
+    }
+}
+
+/// How the cells of a fixture's elements are arranged, for drawing and for
+/// patterns that work from positions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Layout {
+    /// A single row
+    #[default]
+    Linear,
+    /// Rows of `columns` cells, filled left to right from the top
+    Grid { columns: usize },
+    /// A circle, clockwise from the top, with the distance from its centre to
+    /// each cell's centre
+    Ring { radius: Option<f32> },
+}
+
+// Element names in the order their cells are laid out, with numbered
+// elements in numeric order
+fn cell_order(elements: &HashMap<String, Element>) -> Vec<String> {
+    let mut names: Vec<String> = elements.keys().cloned().collect();
+    names.sort_by_key(|name| (name.parse::<u32>().ok().unwrap_or(u32::MAX), name.clone()));
+    names
+}
+
+impl Layout {
+    /// Set the position of every element, except those with an offset of
+    /// their own.
+    pub fn arrange(&self, elements: &mut HashMap<String, Element>) {
+        let names: Vec<String> = cell_order(elements).into_iter()
+            .filter(|name| elements[name].offset.is_none())
+            .collect();
+        let count = names.len();
+
+        for (i, name) in names.iter().enumerate() {
+            let pos = match self {
+                Layout::Linear => (i as f32, 0.0),
+                Layout::Grid { columns } => {
+                    let columns = (*columns).max(1);
+                    ((i % columns) as f32, (i / columns) as f32)
+                },
+                Layout::Ring { radius } => {
+                    // Far enough out for neighbouring cells not to overlap
+                    let radius = radius.unwrap_or_else(|| (count as f32 / TAU).max(1.0));
+                    let angle = TAU * i as f32 / count as f32;
+                    (radius * (1.0 + angle.sin()), radius * (1.0 - angle.cos()))
+                },
+            };
+
+            elements.get_mut(name).unwrap().set_pos(pos.0, pos.1);
+        }
+
+        for element in elements.values_mut() {
+            if let Some((x, y)) = element.offset {
+                element.set_pos(x, y);
+            }
         }
     }
 }
 
+/// Values for a fixture's channels by 1-based channel number. Defaults hold
+/// until an element writes the channel, and fixed values are always sent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelValues {
+    pub defaults: Vec<(usize, u8)>,
+    pub fixed: Vec<(usize, u8)>,
+}
+
+/// How a fixture is placed in the installation, besides its position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// Height above the floor, in the same units as the position
+    pub z: f32,
+    /// Degrees clockwise about the fixture's top left
+    pub rotation: f32,
+    /// The size of each cell, in the same units as the position
+    pub scale: f32,
+    /// The hanging position, such as a truss or boom
+    pub hang: Option<String>,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self { z: 0.0, rotation: 0.0, scale: 1.0, hang: None }
+    }
+}
+
 #[derive(Debug)]
 pub struct Fixture {
     elements: HashMap<String, Element>,
     pos: Point2<f32>,
+    placement: Placement,
     dmx_vec: Vec<u8>,
-    channel: usize,
+    address: Address,
+    channel_values: ChannelValues,
 }
 
 impl Fixture {
     pub fn new(mut elements: HashMap<String, Element>, pos: Point2<f32>,
-               channel: usize, num_channels: usize) -> Self
+               address: Address, num_channels: usize) -> Self
     {
         let mut dmx_vec = vec![];
         dmx_vec.resize(num_channels, 0);
 
-        Element::arrange_linear(&mut elements);
+        Layout::Linear.arrange(&mut elements);
 
         Self {
             elements,
             pos,
+            placement: Placement::default(),
             dmx_vec,
-            channel,
+            address,
+            channel_values: ChannelValues::default(),
         }
     }
 
-    pub fn channel(&self) -> usize {
-        self.channel
+    pub fn set_channel_values(&mut self, channel_values: ChannelValues) {
+        self.channel_values = channel_values;
+        self.reset_dmx();
+    }
+
+    /// Return every channel to its default value.
+    pub fn reset_dmx(&mut self) {
+        for value in self.dmx_vec.iter_mut() {
+            *value = 0;
+        }
+
+        Self::write_channel_values(&mut self.dmx_vec, &self.channel_values.defaults);
+        Self::write_channel_values(&mut self.dmx_vec, &self.channel_values.fixed);
+    }
+
+    fn write_channel_values(dmx: &mut [u8], values: &[(usize, u8)]) {
+        for (channel, value) in values.iter() {
+            if let Some(slot) = dmx.get_mut(channel - 1) {
+                *slot = *value;
+            }
+        }
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
     }
 
     pub fn pos(&self) -> Point2<f32> {
         self.pos
     }
 
+    pub fn set_layout(&mut self, layout: Layout) {
+        layout.arrange(&mut self.elements);
+    }
+
+    /// The width and height of the fixture's cells together, in cells
+    pub fn size(&self) -> (f32, f32) {
+        self.elements.values().fold((1.0, 1.0), |(width, height), element| {
+            (width.max(element.pos.0 + 1.0), height.max(element.pos.1 + 1.0))
+        })
+    }
+
+    /// The centre of an element's cell, in the same units as the fixture's
+    /// position
+    pub fn element_center(&self, name: &str) -> Option<Point2<f32>> {
+        let (x, y) = self.elements.get(name)?.pos;
+        Some(self.to_installation(x + 0.5, y + 0.5))
+    }
+
+    pub fn placement(&self) -> &Placement {
+        &self.placement
+    }
+
+    pub fn set_placement(&mut self, placement: Placement) {
+        self.placement = placement;
+    }
+
+    /// Where a point given in cells from the fixture's top left is in the
+    /// installation, after scaling and rotating the fixture.
+    pub fn to_installation(&self, x: f32, y: f32) -> Point2<f32> {
+        let (sin, cos) = self.placement.rotation.to_radians().sin_cos();
+        let scale = self.placement.scale;
+        self.pos + Vector2::new(x * cos - y * sin, x * sin + y * cos) * scale
+    }
+
     pub fn elements(&self) -> &HashMap<String, Element> {
         &self.elements
     }
@@ -96,59 +557,343 @@ impl Fixture {
         &mut self.elements
     }
 
+    /// Apply time limits, such as smoke machine burst lengths, to elements.
+    pub fn update_timing(&mut self, now: Instant) {
+        for element in self.elements.values_mut() {
+            element.update_smoke(now);
+        }
+    }
+
+    /// Use the same dimmer curve for every element of the fixture.
+    pub fn set_curve(&mut self, curve: DimmerCurve) {
+        for element in self.elements.values_mut() {
+            element.set_curve(curve.clone());
+        }
+    }
+
     pub fn update_dmx(&mut self) {
-        // This only supports basic fixture elements right now
         for (_name, element) in self.elements.iter() {
+            let curve = &element.curve;
+
             match &element.kind {
                 ElementKind::Intensity(intensity) => {
-                    if let Some(channel) = element.channels.get("i") {
-                        self.dmx_vec[(channel - 1) as usize] = (intensity * 255.0) as u8;
-                    }
+                    element.write_fraction(&mut self.dmx_vec, "i", curve.apply(*intensity));
                 },
-                ElementKind::Rgbi(color) => {
-                    if let Some(channel) = element.channels.get("i") {
-                        self.dmx_vec[(channel - 1) as usize] = 255;
-                    }
-
-                    if let Some(channel) = element.channels.get("r") {
-                        self.dmx_vec[(channel - 1) as usize] = (color.r() * 255.0) as u8;
-                    }
-
-                    if let Some(channel) = element.channels.get("g") {
-                        self.dmx_vec[(channel - 1) as usize] = (color.g() * 255.0) as u8;
-                    }
-
-                    if let Some(channel) = element.channels.get("b") {
-                        self.dmx_vec[(channel - 1) as usize] = (color.b() * 255.0) as u8;
-                    }
+                ElementKind::Rgbi{rgb, i} => {
+                    element.write_color_intensity(&mut self.dmx_vec, rgb, *i);
                 },
-                ElementKind::Rgbiu{rgb: color, uv} => {
-                    if let Some(channel) = element.channels.get("i") {
-                        self.dmx_vec[(channel - 1) as usize] = 255;
-                    }
+                ElementKind::Rgbiu{rgb, i, uv} => {
+                    element.write_color_intensity(&mut self.dmx_vec, rgb, *i);
+                    // The dimmer level is for the whole element, UV included
+                    let uv = *uv * i.unwrap_or(1.0);
+                    element.write_fraction(&mut self.dmx_vec, "uv", curve.apply(uv));
+                },
+                ElementKind::Position{pan, tilt} => {
+                    let range = &element.position_range;
+                    let pan = degrees_to_fraction(*pan, range.pan, range.invert_pan);
+                    let tilt = degrees_to_fraction(*tilt, range.tilt, range.invert_tilt);
+                    element.write_fraction(&mut self.dmx_vec, "pan", pan);
+                    element.write_fraction(&mut self.dmx_vec, "tilt", tilt);
+                },
+                ElementKind::Wheel{slot, rotation, scroll} => {
+                    let wheel = &element.wheel;
+                    let slot = wheel.slots.get(*slot).map(|(_, (start, end))| {
+                        // The middle of the slot's range, clear of its neighbours
+                        ((*start as u16 + *end as u16) / 2) as u8
+                    });
 
-                    if let Some(channel) = element.channels.get("r") {
-                        self.dmx_vec[(channel - 1) as usize] = (color.r() * 255.0) as u8;
-                    }
+                    let value = match wheel.scroll.dmx(*scroll) {
+                        Some(value) if *scroll != 0.0 => Some(value),
+                        _ => slot,
+                    };
 
-                    if let Some(channel) = element.channels.get("g") {
-                        self.dmx_vec[(channel - 1) as usize] = (color.g() * 255.0) as u8;
+                    if let Some(value) = value {
+                        element.write_value(&mut self.dmx_vec, "wheel", value);
                     }
 
-                    if let Some(channel) = element.channels.get("b") {
-                        self.dmx_vec[(channel - 1) as usize] = (color.b() * 255.0) as u8;
+                    if let Some(value) = wheel.rotation.dmx(*rotation) {
+                        element.write_value(&mut self.dmx_vec, "rotation", value);
                     }
-
-                    if let Some(channel) = element.channels.get("uv") {
-                        self.dmx_vec[(channel - 1) as usize] = (uv * 255.0) as u8;
+                },
+                ElementKind::Smoke(_) => {
+                    element.write_fraction(&mut self.dmx_vec, "i", element.smoke.output);
+                },
+                ElementKind::Params(values) => {
+                    for (param, value) in element.params.iter().zip(values.iter()) {
+                        element.write_range(&mut self.dmx_vec, &param.name, param.dmx, param.along(*value));
                     }
                 },
                 _ => {}
             }
         }
+
+        Self::write_channel_values(&mut self.dmx_vec, &self.channel_values.fixed);
     }
 
     pub fn dmx(&self) -> &Vec<u8> {
         &self.dmx_vec
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A fixture at the origin, patched at 1.1, whose only element is "1"
+    pub fn single_element_fixture(element: Element, channels: usize) -> Fixture {
+        let mut elements = HashMap::new();
+        elements.insert("1".to_owned(), element);
+        Fixture::new(elements, Point2::new(0.0, 0.0), Address::new(1, 1), channels)
+    }
+
+    fn moving_head(range: PositionRange) -> Fixture {
+        let mut head = Element::new(ElementKind::Position{pan: 0.0, tilt: 0.0});
+        head.add_channel("pan", 1);
+        head.add_channel("pan_fine", 2);
+        head.add_channel("tilt", 3);
+        head.set_position_range(range);
+
+        single_element_fixture(head, 3)
+    }
+
+    fn set_position(fixture: &mut Fixture, pan: f32, tilt: f32) {
+        fixture.elements_mut().get_mut("1").unwrap().set_kind(ElementKind::Position{pan, tilt});
+        fixture.update_dmx();
+    }
+
+    #[test]
+    fn test_position_to_dmx() {
+        let mut fixture = moving_head(PositionRange::default());
+
+        set_position(&mut fixture, 0.0, 0.0);
+        assert_eq!(&vec![0x7f, 0xff, 127], fixture.dmx());
+
+        set_position(&mut fixture, 270.0, -135.0);
+        assert_eq!(&vec![0xff, 0xff, 0], fixture.dmx());
+
+        set_position(&mut fixture, -300.0, 67.5);
+        assert_eq!(&vec![0, 0, 191], fixture.dmx());
+    }
+
+    #[test]
+    fn test_inverted_position() {
+        let mut fixture = moving_head(PositionRange {
+            pan: 360.0,
+            invert_pan: true,
+            ..PositionRange::default()
+        });
+
+        set_position(&mut fixture, 90.0, 0.0);
+        assert_eq!([0x3f, 0xff], fixture.dmx()[..2]);
+    }
+
+    #[test]
+    fn test_channel_values() {
+        let mut par = Element::new(ElementKind::Rgbi{rgb: Color::new(1.0, 0.0, 0.0), i: None});
+        par.add_channel("r", 2);
+
+        let mut fixture = single_element_fixture(par, 4);
+        fixture.set_channel_values(ChannelValues {
+            defaults: vec![(2, 10), (3, 200)],
+            fixed: vec![(4, 99)],
+        });
+        assert_eq!(&vec![0, 10, 200, 99], fixture.dmx());
+
+        fixture.update_dmx();
+        assert_eq!(&vec![0, 255, 200, 99], fixture.dmx());
+
+        fixture.reset_dmx();
+        assert_eq!(&vec![0, 10, 200, 99], fixture.dmx());
+    }
+
+    #[test]
+    fn test_color_mixing() {
+        let emitters = [
+            (vec!["r", "g", "b", "w"], (1.0, 0.5, 0.5), vec![127, 0, 0, 127]),
+            (vec!["r", "g", "b", "a"], (1.0, 0.75, 0.0), vec![0, 0, 0, 255]),
+            (vec!["r", "g", "b", "a", "w"], (1.0, 1.0, 0.5), vec![0, 31, 0, 127, 127]),
+            (vec!["c", "m", "y"], (1.0, 0.0, 0.5), vec![0, 255, 127]),
+        ];
+
+        for (channels, (r, g, b), dmx) in emitters.iter() {
+            let mut element = Element::new(ElementKind::Rgbi{rgb: Color::new(*r, *g, *b), i: None});
+
+            for (i, channel) in channels.iter().enumerate() {
+                element.add_channel(channel, i as u8 + 1);
+            }
+
+            let mut fixture = single_element_fixture(element, channels.len());
+            fixture.update_dmx();
+            assert_eq!(dmx, fixture.dmx());
+        }
+    }
+
+    #[test]
+    fn test_virtual_intensity() {
+        let rgb = Color::new(1.0, 0.5, 0.0);
+
+        for (dimmer, dmx) in [(false, vec![127, 63, 0]), (true, vec![255, 127, 0, 127])].iter() {
+            let mut par = Element::new(ElementKind::Rgbi{rgb: rgb.clone(), i: Some(0.5)});
+            par.add_channel("r", 1);
+            par.add_channel("g", 2);
+            par.add_channel("b", 3);
+
+            if *dimmer {
+                par.add_channel("i", 4);
+            }
+
+            let mut fixture = single_element_fixture(par, dmx.len());
+            fixture.update_dmx();
+            assert_eq!(dmx, fixture.dmx());
+        }
+    }
+
+    #[test]
+    fn test_dimmer_level_includes_uv() {
+        let mut par = Element::new(ElementKind::Rgbiu{rgb: Color::new(1.0, 0.0, 0.0), i: Some(0.5), uv: 1.0});
+        par.add_channel("r", 1);
+        par.add_channel("g", 2);
+        par.add_channel("b", 3);
+        par.add_channel("uv", 4);
+
+        let mut fixture = single_element_fixture(par, 4);
+        fixture.update_dmx();
+        assert_eq!(&vec![127, 0, 0, 127], fixture.dmx());
+    }
+
+    #[test]
+    fn test_smoke_timing() {
+        let mut smoke = Element::new(ElementKind::Smoke(1.0));
+        smoke.add_channel("i", 1);
+        smoke.set_smoke_timing(SmokeTiming {
+            max_burst: Some(Duration::from_secs(10)),
+            cooldown: Duration::from_secs(30),
+        });
+
+        let mut fixture = single_element_fixture(smoke, 1);
+
+        let start = Instant::now();
+        let mut output_at = |seconds: u64, level: f32| {
+            fixture.elements_mut().get_mut("1").unwrap().set_kind(ElementKind::Smoke(level));
+            fixture.update_timing(start + Duration::from_secs(seconds));
+            fixture.update_dmx();
+            fixture.dmx()[0]
+        };
+
+        assert_eq!(255, output_at(0, 1.0));
+        assert_eq!(255, output_at(9, 1.0));
+        assert_eq!(0, output_at(10, 1.0));
+        assert_eq!(0, output_at(39, 1.0));
+        assert_eq!(127, output_at(40, 0.5));
+        assert_eq!(0, output_at(41, 0.0));
+        assert_eq!(0, output_at(42, 1.0));
+        assert_eq!(255, output_at(71, 1.0));
+    }
+
+    #[test]
+    fn test_wheel_to_dmx() {
+        let mut gobo = Element::new(ElementKind::Wheel{slot: 1, rotation: 0.0, scroll: 0.0});
+        gobo.add_channel("wheel", 1);
+        gobo.add_channel("rotation", 2);
+        gobo.set_wheel(Wheel {
+            slots: vec![("open".to_owned(), (0, 7)), ("star".to_owned(), (8, 15))],
+            scroll: SpeedRange { stop: 0, cw: Some((128, 189)), ccw: None },
+            rotation: SpeedRange { stop: 0, cw: Some((5, 127)), ccw: Some((255, 133)) },
+        });
+        assert_eq!(Some(1), gobo.wheel().slot_index("star"));
+
+        let mut fixture = single_element_fixture(gobo, 2);
+        fixture.update_dmx();
+        assert_eq!(&vec![11, 0], fixture.dmx());
+
+        let kinds = [(0.5, -1.0, vec![159, 133]), (-0.5, 0.5, vec![11, 66])];
+
+        for (scroll, rotation, dmx) in kinds.iter() {
+            fixture.elements_mut().get_mut("1").unwrap()
+                .set_kind(ElementKind::Wheel{slot: 1, rotation: *rotation, scroll: *scroll});
+            fixture.update_dmx();
+            assert_eq!(dmx, fixture.dmx());
+        }
+    }
+
+    #[test]
+    fn test_params_to_dmx() {
+        let mut beam = Element::new(ElementKind::Unknown);
+        beam.add_channel("zoom", 1);
+        beam.add_channel("strobe", 2);
+        beam.add_channel("strobe_fine", 3);
+        beam.set_params(vec![
+            Param { name: "zoom".to_owned(), range: (0.0, 255.0), dmx: (0, 255), default: 0.0 },
+            Param { name: "strobe".to_owned(), range: (0.0, 20.0), dmx: (16, 255), default: 0.0 },
+        ]);
+        beam.set_kind(ElementKind::Params(beam.param_defaults()));
+        assert_eq!(Some(1), beam.param_index("strobe"));
+
+        let mut fixture = single_element_fixture(beam, 3);
+        fixture.update_dmx();
+        assert_eq!(&vec![0, 16, 16], fixture.dmx());
+
+        fixture.elements_mut().get_mut("1").unwrap().set_kind(ElementKind::Params(vec![128.0, 40.0]));
+        fixture.update_dmx();
+        assert_eq!(&vec![128, 255, 255], fixture.dmx());
+    }
+
+    #[test]
+    fn test_16_bit_intensity() {
+        let mut dimmer = Element::new(ElementKind::Intensity(0.0));
+        dimmer.add_channel("i", 2);
+        dimmer.add_channel("i_fine", 3);
+        let mut fixture = single_element_fixture(dimmer, 3);
+
+        // 1% is 2.55 in 8 bits, but 655.35 in 16
+        fixture.elements_mut().get_mut("1").unwrap().set_kind(ElementKind::Intensity(0.01));
+        fixture.update_dmx();
+        assert_eq!(&vec![0, 0x02, 0x8f], fixture.dmx());
+
+        fixture.elements_mut().get_mut("1").unwrap().set_kind(ElementKind::Intensity(1.0));
+        fixture.update_dmx();
+        assert_eq!(&vec![0, 0xff, 0xff], fixture.dmx());
+    }
+
+    #[test]
+    fn test_layouts() {
+        let mut elements = HashMap::new();
+
+        for i in 1..=12 {
+            elements.insert(i.to_string(), Element::new(ElementKind::Intensity(0.0)));
+        }
+
+        let mut fixture = Fixture::new(elements, Point2::new(2.0, 1.0), Address::new(1, 1), 12);
+        assert_eq!((9.0, 0.0), fixture.elements()["10"].pos());
+        assert_eq!((12.0, 1.0), fixture.size());
+
+        fixture.set_layout(Layout::Grid { columns: 5 });
+        assert_eq!((4.0, 1.0), fixture.elements()["10"].pos());
+        assert_eq!((5.0, 3.0), fixture.size());
+        assert_eq!(Some(Point2::new(6.5, 2.5)), fixture.element_center("10"));
+
+        fixture.set_layout(Layout::Ring { radius: Some(2.0) });
+        assert_eq!((2.0, 0.0), fixture.elements()["1"].pos());
+        let (x, y) = fixture.elements()["4"].pos();
+        assert!((x - 4.0).abs() < 0.001 && (y - 2.0).abs() < 0.001);
+
+        fixture.elements_mut().get_mut("1").unwrap().set_offset(0.0, 6.0);
+        fixture.set_layout(Layout::Grid { columns: 4 });
+        assert_eq!((0.0, 6.0), fixture.elements()["1"].pos());
+        assert_eq!((0.0, 0.0), fixture.elements()["2"].pos());
+        assert_eq!((4.0, 7.0), fixture.size());
+    }
+
+    #[test]
+    fn test_placement() {
+        let mut elements = HashMap::new();
+        elements.insert("1".to_owned(), Element::new(ElementKind::Intensity(0.0)));
+        elements.insert("2".to_owned(), Element::new(ElementKind::Intensity(0.0)));
+        let mut fixture = Fixture::new(elements, Point2::new(4.0, 2.0), Address::new(1, 1), 2);
+        assert_eq!(Some(Point2::new(5.5, 2.5)), fixture.element_center("2"));
+
+        fixture.set_placement(Placement { rotation: 90.0, scale: 2.0, ..Placement::default() });
+        let center = fixture.element_center("2").unwrap();
+        assert!((center.x - 3.0).abs() < 0.001 && (center.y - 5.0).abs() < 0.001);
+    }
+}
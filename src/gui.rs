@@ -4,28 +4,17 @@ use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event::{MouseButton, KeyCode, KeyMods};
 use ggez::event::{self, EventHandler};
 use ggez::nalgebra::{Point2, Vector2};
-use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
-use std::path::Path;
-use std::thread;
-use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use crate::imgui_wrapper::ImGuiWrapper;
 use crate::installation::Installation;
 use crate::fixture::{Fixture, ElementKind};
-use crate::effect::EffectPool;
 use crate::hitbox::HitboxManager;
 use crate::ggez_util::mutate_from_key;
-use crate::command_input_parser;
-use crate::cue::CueList;
-use crate::effect::Command;
-use crate::command_input_parser::Chunk;
+use crate::engine::{Engine, TICK_RATE};
 
 const INITIAL_WIDTH: f32 = 1000.0;
 const INITIAL_HEIGHT: f32 = 600.0;
 
-pub fn run_gui(installation: Installation, effect_pool: EffectPool,
-               cue_list: CueList, dmx_send: mpsc::Sender<Vec<u8>>)
-{
+pub fn run_gui(engine: Engine) {
     let (mut ctx, mut event_loop) = ContextBuilder::new("my_gui", "Author")
         .window_mode(WindowMode {
             width: INITIAL_WIDTH,
@@ -40,8 +29,7 @@ pub fn run_gui(installation: Installation, effect_pool: EffectPool,
         .build()
         .expect("Could not create ggez context!");
 
-    let mut gui = Visualizer::new(&mut ctx, 1.0, installation, effect_pool,
-                                  cue_list, dmx_send);
+    let mut gui = Visualizer::new(&mut ctx, 1.0, engine);
 
     match event::run(&mut ctx, &mut event_loop, &mut gui) {
         Ok(_) => println!("Exited GUI"),
@@ -49,57 +37,31 @@ pub fn run_gui(installation: Installation, effect_pool: EffectPool,
     }
 }
 
-#[derive(PartialEq)]
-pub enum DmxStatus {
-    Ok,
-    Error,
-    Unknown,
-}
-
-type NotifyRx = Receiver<Result<notify::event::Event, notify::Error>>;
-
 struct Visualizer {
     imgui_wrapper: ImGuiWrapper,
     hidpi_factor: f32,
-    installation: Installation,
-    effect_pool: EffectPool,
-    cue_list: CueList,
-    dmx_send: mpsc::Sender<Vec<u8>>,
-    dmx_chain: Vec<u8>,
+    engine: Engine,
     selected: Vec<String>,
     hitbox_manager: HitboxManager,
     installation_view_origin: Point2<f32>,
     installation_view_scale: f32,
-    dmx_status: DmxStatus,
     command_input_buffer: String,
-    watcher: Option<RecommendedWatcher>,
-    watcher_recv: Option<NotifyRx>,
 }
 
 impl Visualizer {
-    pub fn new(ctx: &mut Context, hidpi_factor: f32,
-               installation: Installation, effect_pool: EffectPool, cue_list: CueList,
-               dmx_send: mpsc::Sender<Vec<u8>>) -> Self
-    {
+    pub fn new(ctx: &mut Context, hidpi_factor: f32, engine: Engine) -> Self {
         let mut visualizer = Self {
             imgui_wrapper: ImGuiWrapper::new(ctx),
             hidpi_factor,
-            installation,
-            effect_pool,
-            cue_list,
-            dmx_send,
-            dmx_chain: vec![],
+            engine,
             selected: vec![],
             hitbox_manager: HitboxManager::new(),
             installation_view_origin: Point2::new(10.0, 10.0),
             installation_view_scale: 40.0,
-            dmx_status: DmxStatus::Unknown,
             command_input_buffer: String::new(),
-            watcher: None,
-            watcher_recv: None,
         };
 
-        visualizer.watch("patterns");
+        visualizer.engine.watch_patterns();
         visualizer.update_hitboxes();
 
         visualizer
@@ -110,23 +72,16 @@ impl Visualizer {
         let scale = self.installation_view_scale;
         self.hitbox_manager.clear();
 
-        for (name, fixture) in self.installation.fixtures() {
+        for (name, fixture) in self.engine.installation().fixtures() {
             let location = fixture.pos();
+            let (width, height) = fixture.size();
+            let cell = scale * fixture.placement().scale;
             let rect = Rect::new(location.x * scale + origin.coords.x,
                                  location.y * scale + origin.coords.y,
-                                 fixture.elements().len() as f32 * 1.0 * scale,
-                                 1.0 * scale);
-            self.hitbox_manager.add(rect, name);
+                                 width * cell, height * cell);
+            self.hitbox_manager.add(rect, fixture.placement().rotation.to_radians(), name);
         }
     }
-
-    fn watch<P: AsRef<Path>>(&mut self, path: P) {
-        let (tx, rx) = std::sync::mpsc::channel();
-        let mut watcher: RecommendedWatcher = Watcher::new_immediate(move |res| tx.send(res).unwrap()).unwrap();
-        watcher.watch(path, RecursiveMode::Recursive).unwrap();
-        self.watcher = Some(watcher);
-        self.watcher_recv = Some(rx);
-    }
 }
 
 lazy_static! {
@@ -147,19 +102,25 @@ fn render_installation(ctx: &mut Context, installation: &Installation, selected:
 fn draw_fixture(ctx: &mut Context, fixture: &Fixture, name: &str, is_selected: bool,
                 origin: Point2<f32>, scale: f32)
 {
-    let location = fixture.pos();
-    let origin = origin.coords;
+    let (width, height) = fixture.size();
+    let rotation = fixture.placement().rotation.to_radians();
+    // Fixtures are drawn in cells, this many pixels across
+    let cell = scale * fixture.placement().scale;
+
+    // Where a point in cells from the fixture's top left is drawn
+    let view = |x: f32, y: f32| fixture.to_installation(x, y) * scale + origin.coords;
+    let fixture_param = DrawParam::default().dest(view(0.0, 0.0)).rotation(rotation);
 
     let background = graphics::Mesh::new_rectangle(
         ctx,
         graphics::DrawMode::fill(),
-        Rect::new(0.0, 0.0, fixture.elements().len() as f32 * 1.0 * scale, 1.0 * scale),
+        Rect::new(0.0, 0.0, width * cell, height * cell),
         *COLOR_FIXTURE_BG,
     ).unwrap();
-    graphics::draw(ctx, &background, DrawParam::default().dest(location * scale + origin)).unwrap();
+    graphics::draw(ctx, &background, fixture_param).unwrap();
 
     for (_name, element) in fixture.elements().iter() {
-        let i = element.pos().0;
+        let (x, y) = element.pos();
 
         match element.kind() {
             ElementKind::Intensity(intensity) => {
@@ -167,36 +128,81 @@ fn draw_fixture(ctx: &mut Context, fixture: &Fixture, name: &str, is_selected: b
                 let circle = graphics::Mesh::new_circle(
                     ctx,
                     graphics::DrawMode::fill(),
-                    Point2::new(i as f32 * 1.0 + 0.5, 0.5) * scale,
-                    0.5 * scale,
+                    Point2::new(x + 0.5, y + 0.5) * cell,
+                    0.5 * cell,
                     0.001,
                     color,
                 ).unwrap();
-                graphics::draw(ctx, &circle, DrawParam::default().dest(location * scale + origin)).unwrap();
+                graphics::draw(ctx, &circle, fixture_param).unwrap();
             },
-            ElementKind::Rgbi(color) => {
-                let color = graphics::Color::new(color.r(), color.g(), color.b(), 1.0);
+            ElementKind::Smoke(level) => {
+                // Held back smoke shows as a dim ring around the output
+                let output = element.smoke_output();
+                let ring = graphics::Mesh::new_circle(
+                    ctx,
+                    graphics::DrawMode::stroke(2.0),
+                    Point2::new(x + 0.5, y + 0.5) * cell,
+                    0.45 * cell,
+                    0.001,
+                    graphics::Color::new(0.6, 0.6, 0.6, level.clamp(0.0, 1.0)),
+                ).unwrap();
+                graphics::draw(ctx, &ring, fixture_param).unwrap();
+
+                let color = graphics::Color::new(0.8, 0.8, 0.8, output);
                 let circle = graphics::Mesh::new_circle(
                     ctx,
                     graphics::DrawMode::fill(),
-                    Point2::new(i as f32 * 1.0 + 0.5, 0.5) * scale,
-                    0.5 * scale,
+                    Point2::new(x + 0.5, y + 0.5) * cell,
+                    0.4 * cell,
                     0.001,
                     color,
                 ).unwrap();
-                graphics::draw(ctx, &circle, DrawParam::default().dest(location * scale + origin)).unwrap();
+                graphics::draw(ctx, &circle, fixture_param).unwrap();
             },
-            ElementKind::Rgbiu{rgb: color, ..} => {
-                let color = graphics::Color::new(color.r(), color.g(), color.b(), 1.0);
+            ElementKind::Rgbi{rgb: color, i: intensity} | ElementKind::Rgbiu{rgb: color, i: intensity, ..} => {
+                let intensity = intensity.unwrap_or(1.0).clamp(0.0, 1.0);
+                let color = graphics::Color::new(color.r() * intensity, color.g() * intensity,
+                                                 color.b() * intensity, 1.0);
                 let circle = graphics::Mesh::new_circle(
                     ctx,
                     graphics::DrawMode::fill(),
-                    Point2::new(i as f32 * 1.0 + 0.5, 0.5) * scale,
-                    0.5 * scale,
+                    Point2::new(x + 0.5, y + 0.5) * cell,
+                    0.5 * cell,
                     0.001,
                     color,
                 ).unwrap();
-                graphics::draw(ctx, &circle, DrawParam::default().dest(location * scale + origin)).unwrap();
+                graphics::draw(ctx, &circle, fixture_param).unwrap();
+            }
+            ElementKind::Position{pan, ..} => {
+                // Point in the direction of pan, with 0 degrees straight up
+                let center = Point2::new(x + 0.5, y + 0.5) * cell;
+                let angle = pan.to_radians();
+                let tip = center + Vector2::new(angle.sin(), -angle.cos()) * 0.4 * cell;
+                let line = graphics::Mesh::new_line(ctx, &[center, tip], 2.0, graphics::WHITE).unwrap();
+                graphics::draw(ctx, &line, fixture_param).unwrap();
+            }
+            ElementKind::Wheel{slot, scroll, ..} => {
+                let label = match element.wheel().slot_name(*slot) {
+                    Some(_) if *scroll != 0.0 => Text::new("scroll"),
+                    Some(name) => Text::new(name),
+                    None => Text::new(format!("#{}", slot)),
+                };
+                let draw_param = fixture_param
+                    .dest(view(x, y + 0.4))
+                    .color(graphics::WHITE)
+                    .scale(Vector2::new(0.7, 0.7));
+                graphics::draw(ctx, &label, draw_param).unwrap();
+            }
+            ElementKind::Params(values) => {
+                let lines: Vec<String> = element.params().iter().zip(values.iter())
+                    .map(|(param, value)| format!("{} {:.0}", param.name, value))
+                    .collect();
+                let label = Text::new(lines.join("\n"));
+                let draw_param = fixture_param
+                    .dest(view(x, y))
+                    .color(graphics::WHITE)
+                    .scale(Vector2::new(0.7, 0.7));
+                graphics::draw(ctx, &label, draw_param).unwrap();
             }
             _ => {},
         }
@@ -206,82 +212,61 @@ fn draw_fixture(ctx: &mut Context, fixture: &Fixture, name: &str, is_selected: b
     let outline = graphics::Mesh::new_rectangle(
         ctx,
         graphics::DrawMode::stroke(1.0),
-        Rect::new(0.0, 0.0, fixture.elements().len() as f32 * 1.0 * scale, 1.0 * scale),
+        Rect::new(0.0, 0.0, width * cell, height * cell),
         *COLOR_FIXTURE_OUTLINE,
     ).unwrap();
-    graphics::draw(ctx, &outline, DrawParam::default().dest(location * scale + origin)).unwrap();
+    graphics::draw(ctx, &outline, fixture_param).unwrap();
 
     if is_selected {
         let outline = graphics::Mesh::new_rectangle(
             ctx,
             graphics::DrawMode::stroke(1.0),
-            Rect::new(0.0, 0.0, fixture.elements().len() as f32 * 1.0 * scale, 1.0 * scale),
+            Rect::new(0.0, 0.0, width * cell, height * cell),
             *COLOR_FIXTURE_OUTLINE_SELECTED,
         ).unwrap();
-        graphics::draw(ctx, &outline, DrawParam::default().dest(location * scale + origin)).unwrap();
+        graphics::draw(ctx, &outline, fixture_param).unwrap();
 
         for (name, element) in fixture.elements().iter() {
-            let i = element.pos().0;
+            let (x, y) = element.pos();
             let label = Text::new(name.clone());
             let dim = label.dimensions(ctx);
-            let draw_param = DrawParam::default()
-                .dest(location * scale + origin + Vector2::new(i as f32 * 1.0 * scale, 1.0 * scale - dim.1 as f32))
+            let draw_param = fixture_param
+                .dest(view(x, y + 1.0 - dim.1 as f32 / cell))
                 .color(graphics::WHITE);
             graphics::draw(ctx, &label, draw_param).unwrap();
         }
 
-        let channel_text = Text::new(format!("CH {}", fixture.channel()));
-        let draw_param = DrawParam::default()
-            .dest(location * scale + origin + Vector2::new(0.0, 1.0 * scale))
+        let details = match &fixture.placement().hang {
+            Some(hang) => format!("CH {}\n{}", fixture.address(), hang),
+            None => format!("CH {}", fixture.address()),
+        };
+        let channel_text = Text::new(details);
+        let draw_param = fixture_param
+            .dest(view(0.0, height))
             .color(graphics::WHITE)
             .scale(Vector2::new(0.8, 0.8));
         graphics::draw(ctx, &channel_text, draw_param).unwrap();
     }
 
     let label = Text::new(name);
-    graphics::draw(ctx, &label, (location * scale + origin, graphics::WHITE)).unwrap();
+    graphics::draw(ctx, &label, fixture_param.color(graphics::WHITE)).unwrap();
 }
 
 impl EventHandler for Visualizer {
     fn update(&mut self, ctx: &mut Context) -> GameResult<()> {
-        if !ggez::timer::check_update_time(ctx, 30) {
+        if !ggez::timer::check_update_time(ctx, TICK_RATE) {
             return Ok(())
         }
 
-        if let Some(rx) = &self.watcher_recv {
-            // Drain all events, then reload patterns, since many events can
-            // be received at once.
-            // todo Consider a debounce.
-            let mut changed = false;
-
-            while rx.try_recv().is_ok() {
-                changed = true;
-            }
-
-            if changed {
-                self.effect_pool.reload_patterns();
-            }
-        }
-
-        self.effect_pool.run_commands();
-        self.effect_pool.apply_to(&mut self.installation);
-
-        let chain = self.installation.build_dmx_chain();
-        self.dmx_chain = chain.clone();
-        self.dmx_status = match self.dmx_send.send(chain) {
-            Ok(_) => DmxStatus::Ok,
-            Err(_) => DmxStatus::Error,
-        };
+        self.engine.tick();
         Ok(())
     }
 
     fn draw(&mut self, ctx: &mut Context) -> GameResult<()> {
         graphics::clear(ctx, graphics::BLACK);
-        render_installation(ctx, &self.installation, &self.selected,
+        render_installation(ctx, self.engine.installation(), &self.selected,
                             self.installation_view_origin, self.installation_view_scale);
-        self.imgui_wrapper.render(ctx, self.hidpi_factor, &mut self.effect_pool,
-                                  &self.cue_list,
-                                  &self.dmx_status, &self.dmx_chain,
+        self.imgui_wrapper.render(ctx, self.hidpi_factor, &mut self.engine,
                                   &self.command_input_buffer);
         graphics::present(ctx)
     }
@@ -320,30 +305,10 @@ impl EventHandler for Visualizer {
 
         match keycode {
             KeyCode::Return | KeyCode::NumpadEnter => {
-                let chunks = command_input_parser::parse(&self.command_input_buffer);
-                let commands = expand_cues(chunks, &self.cue_list);
-                self.effect_pool.add_commands(commands);
+                self.engine.run_command_line(&self.command_input_buffer);
                 self.command_input_buffer.clear();
             },
             _ => {},
         }
     }
 }
-
-pub fn expand_cues(chunks: Vec<Chunk>, cue_list: &CueList) -> Vec<Command> {
-    let mut commands = vec![];
-
-    for chunk in chunks {
-        match chunk {
-            Chunk::Effect(e) => commands.push(e),
-            Chunk::CueNum(i) => {
-                let command = cue_list.cue_command(i - 1);
-                let chunks = command_input_parser::parse(&command.unwrap());
-                let mut result = expand_cues(chunks, cue_list);
-                commands.append(&mut result);
-            }
-        }
-    }
-
-    commands
-}
@@ -0,0 +1,94 @@
+use std::io::{self, BufRead, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::engine::{Engine, TICK_RATE};
+use crate::dmx_control::OutputState;
+
+/// Run the show without a window. Command lines ("a1 b2", "3", ...) are read
+/// from stdin and, if an address is given, from TCP connections to it.
+/// A line of "quit" stops the show.
+pub fn run_headless(mut engine: Engine, command_addr: Option<SocketAddr>) {
+    let (command_send, command_recv) = mpsc::channel();
+
+    let stdin_send = command_send.clone();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        read_commands(stdin.lock(), &stdin_send);
+    });
+
+    if let Some(addr) = command_addr {
+        match TcpListener::bind(addr) {
+            Ok(listener) => {
+                println!("Listening for commands on {}", addr);
+                thread::spawn(move || accept_commands(listener, command_send));
+            },
+            Err(e) => println!("Unable to listen for commands on {}: {}", addr, e),
+        }
+    }
+
+    engine.watch_patterns();
+
+    let period = Duration::from_secs(1) / TICK_RATE;
+    let mut next_tick = Instant::now();
+    let mut last_states = vec![];
+
+    loop {
+        while let Ok(line) = command_recv.try_recv() {
+            if line.trim() == "quit" {
+                return;
+            }
+
+            engine.run_command_line(line.trim());
+        }
+
+        engine.tick();
+
+        let states: Vec<(String, OutputState)> = engine.dmx_status().iter()
+            .map(|status| (status.name.clone(), status.state.clone()))
+            .collect();
+
+        if states != last_states {
+            for (name, state) in states.iter() {
+                println!("Output {}: {:?}", name, state);
+            }
+
+            last_states = states;
+        }
+
+        next_tick += period;
+        let now = Instant::now();
+
+        if next_tick > now {
+            thread::sleep(next_tick - now);
+        } else {
+            next_tick = now;
+        }
+    }
+}
+
+fn read_commands<R: BufRead>(reader: R, send: &mpsc::Sender<String>) {
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+
+        if send.send(line).is_err() {
+            return;
+        }
+    }
+}
+
+fn accept_commands(listener: TcpListener, send: mpsc::Sender<String>) {
+    for stream in listener.incoming() {
+        let stream: TcpStream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+
+        let send = send.clone();
+        thread::spawn(move || read_commands(BufReader::new(stream), &send));
+    }
+}
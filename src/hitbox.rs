@@ -3,6 +3,8 @@ use ggez::graphics::Rect;
 
 pub struct Hitbox {
     rect: Rect,
+    // Radians clockwise about the rect's top left
+    rotation: f32,
     target: String,
 }
 
@@ -21,9 +23,11 @@ impl HitboxManager {
         self.hitboxes = vec![];
     }
 
-    pub fn add(&mut self, rect: Rect, target: &str) {
+    /// Add a rect turned `rotation` radians clockwise about its top left.
+    pub fn add(&mut self, rect: Rect, rotation: f32, target: &str) {
         let hitbox = Hitbox {
             rect,
+            rotation,
             target: target.to_owned(),
         };
 
@@ -32,6 +36,12 @@ impl HitboxManager {
 
     pub fn targets_at(&mut self, p: Point2<f32>) -> Vec<String> {
         self.hitboxes.iter().filter_map(|hitbox| {
+            // Turn the point back to where it would be if the rect weren't
+            let (sin, cos) = hitbox.rotation.sin_cos();
+            let (dx, dy) = (p.x - hitbox.rect.x, p.y - hitbox.rect.y);
+            let p = Point2::new(hitbox.rect.x + dx * cos + dy * sin,
+                                hitbox.rect.y - dx * sin + dy * cos);
+
             if hitbox.rect.contains(p) {
                 Some(hitbox.target.to_owned())
             } else {
@@ -6,8 +6,10 @@ use gfx_core::handle::RenderTargetView;
 use gfx_device_gl;
 use ggez::{graphics, Context};
 use crate::effect::EffectPool;
-use crate::cue::CueList;
-use crate::gui::DmxStatus;
+use crate::dmx_control::OutputState;
+use crate::merge::Source;
+use crate::park::ParkTarget;
+use crate::engine::Engine;
 
 #[derive(Copy, Clone, PartialEq, Debug, Default)]
 struct MouseState {
@@ -107,9 +109,7 @@ impl ImGuiWrapper {
     }
 
     pub fn render(&mut self, ctx: &mut Context, hidpi_factor: f32,
-                  effect_pool: &mut EffectPool, cue_list: &CueList,
-                  dmx_status: &DmxStatus, dmx_chain: &[u8],
-                  command_input_buffer: &str)
+                  engine: &mut Engine, command_input_buffer: &str)
     {
         self.update_mouse();
 
@@ -129,18 +129,73 @@ impl ImGuiWrapper {
         imgui::Window::new(im_str!("Effect Pool"))
           .size([300.0, 300.0], imgui::Condition::FirstUseEver)
           .position([100.0, 100.0], imgui::Condition::FirstUseEver)
-          .build(&ui, || { effect_pool_ui(&ui, effect_pool) });
+          .build(&ui, || { effect_pool_ui(&ui, engine.effect_pool_mut()) });
 
         imgui::Window::new(im_str!("DMX Channels"))
           .size([300.0, 300.0], imgui::Condition::FirstUseEver)
           .position([100.0, 300.0], imgui::Condition::FirstUseEver)
           .build(&ui, || {
-            if dmx_status != &DmxStatus::Ok {
-                ui.text(im_str!("Sending failed!"));
+            let dmx_status = engine.dmx_status();
+
+            if dmx_status.is_empty() {
+                ui.text(im_str!("No output status"));
+            }
+
+            for status in dmx_status {
+                match &status.state {
+                    OutputState::Connecting => {
+                        ui.text(im_str!("{}: connecting", status.name));
+                    },
+                    OutputState::Connected => {
+                        ui.text(im_str!("{}: connected, {:.1} fps", status.name, status.stats.frame_rate));
+                    },
+                    OutputState::Disconnected(e) => {
+                        ui.text_colored([1.0, 0.6, 0.0, 1.0],
+                                        im_str!("{}: disconnected ({}), retrying", status.name, e));
+                    },
+                    OutputState::Error(e) => {
+                        ui.text_colored([1.0, 0.2, 0.2, 1.0], im_str!("{}: error ({})", status.name, e));
+                    },
+                    OutputState::Stopped(e) => {
+                        ui.text_colored([1.0, 0.2, 0.2, 1.0], im_str!("{}: stopped ({})", status.name, e));
+                    },
+                }
+
+                let stats = &status.stats;
+                ui.text(im_str!("  sent {}, skipped {}, late {}, max {:.1} ms",
+                                stats.frames_sent, stats.frames_skipped, stats.late_frames,
+                                stats.max_send_time.as_secs_f32() * 1000.0));
             }
 
-            for (i, channel) in dmx_chain.iter().enumerate() {
-                ui.text(im_str!("{}: {}", i + 1, channel));
+            let merger = engine.merger();
+
+            for (name, universes) in merger.inputs() {
+                if universes.is_empty() {
+                    ui.text_colored([1.0, 0.6, 0.0, 1.0], im_str!("{} (input): no data", name));
+                } else {
+                    let universes: Vec<String> = universes.iter().map(|u| u.to_string()).collect();
+                    ui.text(im_str!("{} (input): receiving universe {}", name, universes.join(", ")));
+                }
+            }
+
+            ui.separator();
+
+            for (universe, chain) in engine.dmx_chain().iter() {
+                if !ui.collapsing_header(&im_str!("Universe {}", universe)).default_open(true).build() {
+                    continue;
+                }
+
+                let owners = merger.owners().get(universe);
+
+                for (i, channel) in chain.iter().enumerate() {
+                    match owners.map(|owners| owners[i]) {
+                        Some(Source::Input(input)) => {
+                            ui.text_colored([0.4, 0.8, 1.0, 1.0], im_str!("{}.{:03}: {} < {}",
+                                            universe, i + 1, channel, merger.input_name(input)));
+                        },
+                        _ => ui.text(im_str!("{}.{:03}: {}", universe, i + 1, channel)),
+                    }
+                }
             }
         });
 
@@ -156,11 +211,37 @@ impl ImGuiWrapper {
             .size([200.0, 400.0], imgui::Condition::FirstUseEver)
             .position([200.0, 200.0], imgui::Condition::FirstUseEver)
             .build(&ui, || {
-                for cue in cue_list.cues() {
+                for cue in engine.cue_list().cues() {
                     ui.text(im_str!("{} {}", cue.name(), cue.command()));
                 }
         });
 
+        imgui::Window::new(im_str!("Parked"))
+            .size([200.0, 200.0], imgui::Condition::FirstUseEver)
+            .position([300.0, 300.0], imgui::Condition::FirstUseEver)
+            .build(&ui, || {
+                let parks = engine.installation().parks().to_vec();
+
+                if parks.is_empty() {
+                    ui.text(im_str!("Nothing parked"));
+                } else if ui.small_button(im_str!("Clear all")) {
+                    engine.installation_mut().unpark_all();
+                }
+
+                for (i, (target, value)) in parks.iter().enumerate() {
+                    if ui.small_button(&im_str!("Clear##park{}", i)) {
+                        engine.installation_mut().unpark(target);
+                    }
+
+                    ui.same_line(0.0);
+
+                    match target {
+                        ParkTarget::Element{..} => ui.text(im_str!("{} at 0x{:x}", target, value)),
+                        ParkTarget::Address(_) => ui.text(im_str!("{} at {}", target, value)),
+                    }
+                }
+        });
+
         window_rounding.pop(&ui);
 
         let (factory, _, encoder, _, render_target) = graphics::gfx_objects(ctx);
@@ -1,25 +1,48 @@
 use std::collections::HashMap;
+use std::time::Instant;
 use crate::fixture::{Fixture, ElementKind, Element};
 use crate::light::Color;
 use crate::installation_loader;
+use crate::check::Diagnostic;
+use crate::library::Library;
+use crate::patch::{Address, Universes, UNIVERSE_SIZE};
+use crate::park::{self, ParkTarget};
+use crate::pattern::ElementPosition;
 
 pub struct Installation {
-    fixtures: HashMap<String, Fixture>
+    fixtures: HashMap<String, Fixture>,
+    // Applied over whatever effects set, in the order they were parked
+    parks: Vec<(ParkTarget, i32)>,
 }
 
 impl Installation {
     pub fn new(fixtures: HashMap<String, Fixture>) -> Self {
-        Self { fixtures }
+        Self { fixtures, parks: vec![] }
     }
 
-    pub fn new_from_config(config_file: &str) -> Installation {
-        installation_loader::build_from_config(config_file)
+    pub fn new_from_config(config_file: &str, library: &Library) -> Result<Installation, Diagnostic> {
+        installation_loader::build_from_config(config_file, library)
     }
 
     pub fn fixtures(&self) -> &HashMap<String, Fixture> {
         &self.fixtures
     }
 
+    /// Where the centre of an element's cell is, with its fixture's height
+    /// and hanging position.
+    pub fn element_position(&self, fixture: &str, element: &str) -> Option<ElementPosition> {
+        let fixture = self.fixtures.get(fixture)?;
+        let center = fixture.element_center(element)?;
+        let placement = fixture.placement();
+
+        Some(ElementPosition {
+            x: center.x,
+            y: center.y,
+            z: placement.z,
+            hang: placement.hang.clone(),
+        })
+    }
+
     pub fn find_element(&mut self, fixture: &str, element: &str) -> Option<&mut Element> {
         let fixture = match self.fixtures.get_mut(fixture) {
             Some(f) => f,
@@ -29,43 +52,143 @@ impl Installation {
         fixture.elements_mut().get_mut(element)
     }
 
+    /// Pin a fixture element or DMX address to a value until it's unparked.
+    /// Parking the same target again replaces its value. Elements that don't
+    /// take a single value, such as positions and parameters, can't be
+    /// parked; park their DMX addresses instead.
+    pub fn park(&mut self, target: ParkTarget, value: i32) -> Result<(), String> {
+        match &target {
+            ParkTarget::Element{fixture, element} => match self.find_element(fixture, element) {
+                None => return Err(format!("No element {}", target)),
+                Some(element) if park::parked_kind(element.kind(), value).is_none() => {
+                    return Err(format!("Element {} can't be parked", target));
+                },
+                Some(_) => {},
+            },
+            ParkTarget::Address(address) => {
+                if address.universe == 0 || address.channel == 0
+                    || address.channel as usize > UNIVERSE_SIZE
+                {
+                    return Err(format!("No DMX address {}", address));
+                }
+            },
+        }
+
+        match self.parks.iter_mut().find(|(parked, _)| *parked == target) {
+            Some(park) => park.1 = value,
+            None => self.parks.push((target, value)),
+        }
+
+        Ok(())
+    }
+
+    pub fn unpark(&mut self, target: &ParkTarget) -> bool {
+        let count = self.parks.len();
+        self.parks.retain(|(parked, _)| parked != target);
+        self.parks.len() != count
+    }
+
+    pub fn unpark_all(&mut self) {
+        self.parks.clear();
+    }
+
+    pub fn parks(&self) -> &[(ParkTarget, i32)] {
+        &self.parks
+    }
+
+    /// Addresses to cut to zero if the show stops sending frames: the
+    /// outputs of smoke machines.
+    pub fn stall_cut(&self) -> Vec<Address> {
+        let mut addresses = vec![];
+
+        for fixture in self.fixtures.values() {
+            let address = fixture.address();
+
+            for element in fixture.elements().values() {
+                if let ElementKind::Smoke(_) = element.kind() {
+                    for channel in ["i", "i_fine"].iter().filter_map(|name| element.channel(name)) {
+                        addresses.push(Address::new(address.universe, address.channel + channel as u16 - 1));
+                    }
+                }
+            }
+        }
+
+        addresses
+    }
+
     pub fn zero(&mut self) {
         for (_name, fixture) in self.fixtures.iter_mut() {
+            fixture.reset_dmx();
+
             for (_name, element) in fixture.elements_mut().iter_mut() {
                 match element.kind() {
                     ElementKind::Intensity(_) => element.set_kind(ElementKind::Intensity(0.0)),
-                    ElementKind::Rgbi(_) => element.set_kind(ElementKind::Rgbi(Color::black())),
+                    ElementKind::Rgbi{..} => {
+                        element.set_kind(ElementKind::Rgbi{rgb: Color::black(), i: None});
+                    }
                     ElementKind::Rgbiu{..} => {
                         element.set_kind(ElementKind::Rgbiu{
                             rgb: Color::black(),
+                            i: None,
                             uv: 0.0,
                         });
                     }
+                    ElementKind::Smoke(_) => element.set_kind(ElementKind::Smoke(0.0)),
+                    ElementKind::Position{..} => {
+                        element.set_kind(ElementKind::Position{pan: 0.0, tilt: 0.0});
+                    }
+                    ElementKind::Wheel{..} => {
+                        element.set_kind(ElementKind::Wheel{slot: 0, rotation: 0.0, scroll: 0.0});
+                    }
+                    ElementKind::Params(_) => {
+                        element.set_kind(ElementKind::Params(element.param_defaults()));
+                    }
                     _ => {}
                 }
             }
         }
     }
 
-    pub fn build_dmx_chain(&mut self) -> Vec<u8> {
-        let mut chain = vec![];
+    pub fn build_dmx_chain(&mut self, now: Instant) -> Universes {
+        let mut universes = Universes::new();
+
+        for (target, value) in self.parks.iter() {
+            if let ParkTarget::Element{fixture, element} = target {
+                let element = self.fixtures.get_mut(fixture)
+                                  .and_then(|fixture| fixture.elements_mut().get_mut(element));
+
+                if let Some(element) = element {
+                    if let Some(kind) = park::parked_kind(element.kind(), *value) {
+                        element.set_kind(kind);
+                    }
+                }
+            }
+        }
 
         for (_name, fixture) in self.fixtures.iter_mut() {
+            fixture.update_timing(now);
             fixture.update_dmx();
-            let fixture_dmx = fixture.dmx().to_vec();
-            let channel = fixture.channel() - 1;
-
-            let required_length = channel + fixture_dmx.len();
+            let address = fixture.address();
+            let chain = universes.entry(address.universe)
+                                 .or_insert_with(|| vec![0; UNIVERSE_SIZE]);
 
-            if chain.len() < required_length {
-                chain.resize(required_length, 0);
+            // Patches are checked at load time, but never write past the
+            // end of the universe.
+            for (i, val) in fixture.dmx().iter().enumerate() {
+                if let Some(slot) = chain.get_mut(address.index() + i) {
+                    *slot = *val;
+                }
             }
+        }
 
-            for (i, val) in fixture_dmx.iter().enumerate() {
-                chain[channel + i] = *val;
+        for (target, value) in self.parks.iter() {
+            if let ParkTarget::Address(address) = target {
+                let chain = universes.entry(address.universe)
+                                     .or_insert_with(|| vec![0; UNIVERSE_SIZE]);
+                chain[address.index()] = (*value).clamp(0, 255) as u8;
             }
         }
 
-        chain
+        universes
     }
 }
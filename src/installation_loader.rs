@@ -1,22 +1,330 @@
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
 use serde::Deserialize;
+use toml::Spanned;
 use std::fs::read_to_string;
+use std::time::Duration;
 use nalgebra::Point2;
 use crate::installation::Installation;
-use crate::fixture::{Fixture, Element, ElementKind};
-use crate::light::Color;
+use crate::fixture::{Fixture, Element, ElementKind, PositionRange, Wheel, SpeedRange, Param,
+                     ChannelValues, SmokeTiming, Layout, Placement};
+use crate::light::{Color, DimmerCurve};
+use crate::patch::{self, Address, AddressRange};
+use crate::output::{self, OutputConfig, OutputKind, OutputTiming};
+use crate::dmx_input::{InputConfig, InputKind};
+use crate::merge::{MergeRules, MergeMode};
+use crate::sacn::{self, SacnConfig, Destination};
+use crate::artnet;
+use crate::check::{self, Diagnostic};
+use crate::library::Library;
 
 #[derive(Deserialize, Debug)]
 struct InstallationConfig {
     fixtures: HashMap<String, FixtureConfig>,
+    outputs: Option<HashMap<String, OutputEntryConfig>>,
+    inputs: Option<HashMap<String, InputKindConfig>>,
+    merge: Option<MergeConfig>,
 }
 
 #[derive(Deserialize, Debug)]
 struct FixtureConfig {
-    kind: String,
-    channel: u8,
-    mode: String,
+    kind: Spanned<String>,
+    #[serde(alias = "channel")]
+    address: AddressConfig,
+    mode: Spanned<String>,
     pos: (f32, f32),
+    z: Option<f32>,
+    rotation: Option<f32>,
+    scale: Option<f32>,
+    hang: Option<String>,
+    curve: Option<CurveConfig>,
+}
+
+impl FixtureConfig {
+    fn placement(&self) -> Placement {
+        let mut placement = Placement::default();
+
+        if let Some(z) = self.z {
+            placement.z = z;
+        }
+
+        if let Some(rotation) = self.rotation {
+            placement.rotation = rotation;
+        }
+
+        if let Some(scale) = self.scale {
+            placement.scale = scale;
+        }
+
+        placement.hang = self.hang.clone();
+        placement
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum AddressConfig {
+    Channel(u16),
+    Address(String),
+}
+
+impl AddressConfig {
+    fn parse(&self) -> Result<Address, String> {
+        match self {
+            AddressConfig::Channel(channel) => Ok(Address::new(1, *channel)),
+            AddressConfig::Address(s) => s.parse(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct OutputEntryConfig {
+    #[serde(flatten)]
+    kind: OutputKindConfig,
+    rate: Option<f32>,
+    send_on_change: Option<bool>,
+    keep_alive: Option<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum OutputKindConfig {
+    Serial {
+        device: Option<String>,
+        universe: Option<u16>,
+        break_us: Option<u64>,
+    },
+    Artnet {
+        target: String,
+        universe: Option<u16>,
+        bind: Option<String>,
+    },
+    Sacn {
+        target: Option<String>,
+        universe: Option<u16>,
+        priority: Option<u8>,
+        source_name: Option<String>,
+        cid: Option<String>,
+    },
+    File {
+        path: String,
+    },
+    Null,
+}
+
+// Durations from the configuration, which Duration would panic on if they
+// were negative, NaN or too long
+fn seconds(name: &str, value: f32) -> Result<Duration, String> {
+    if value >= 0.0 && value <= u32::MAX as f32 {
+        Ok(Duration::from_secs_f32(value))
+    } else {
+        Err(format!("{} should be a number of seconds, not {}", name, value))
+    }
+}
+
+impl OutputEntryConfig {
+    fn timing(&self) -> Result<OutputTiming, String> {
+        let mut timing = OutputTiming::default();
+
+        if let Some(rate) = self.rate {
+            if !(rate > 0.0 && rate <= output::MAX_RATE) {
+                return Err(format!("rate should be a number of frames per second up to {}, not {}",
+                                   output::MAX_RATE, rate));
+            }
+
+            timing.rate = rate;
+        }
+
+        if let Some(send_on_change) = self.send_on_change {
+            timing.send_on_change = send_on_change;
+        }
+
+        if let Some(keep_alive) = self.keep_alive {
+            timing.keep_alive = seconds("keep_alive", keep_alive)?;
+        }
+
+        Ok(timing)
+    }
+
+    fn output(&self, name: &str, artnet_bind: &str) -> Result<OutputConfig, String> {
+        let mut output = OutputConfig::new(name, self.kind.clone().into_kind(artnet_bind)?);
+        output.timing = self.timing()?;
+        Ok(output)
+    }
+}
+
+// Bind addresses are an IP address and port, so they can be checked without
+// looking anything up
+fn check_bind(bind: &str) -> Result<(), String> {
+    match bind.parse::<SocketAddr>() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(format!("bind {} should be an address and port, such as 0.0.0.0:6454", bind)),
+    }
+}
+
+// Universes from the configuration, and `count` after them, must be ones the
+// protocol has
+fn check_universes(first: u16, count: u16, range: RangeInclusive<u16>) -> Result<(), String> {
+    let last = first as u32 + count.max(1) as u32 - 1;
+
+    if range.contains(&first) && last <= *range.end() as u32 {
+        Ok(())
+    } else if count <= 1 {
+        Err(format!("universe {} should be {} to {}", first, range.start(), range.end()))
+    } else {
+        Err(format!("universes {} to {} should be within {} to {}",
+                    first, last, range.start(), range.end()))
+    }
+}
+
+impl OutputKindConfig {
+    /// The output, with Art-Net sent from `artnet_bind` unless it says
+    /// otherwise.
+    fn into_kind(self, artnet_bind: &str) -> Result<OutputKind, String> {
+        Ok(match self {
+            OutputKindConfig::Serial{device, universe, break_us} => OutputKind::Serial {
+                device: device.unwrap_or_else(|| "/dev/ttyUSB0".to_owned()),
+                universe: universe.unwrap_or(1),
+                break_us: break_us.unwrap_or(136),
+            },
+            OutputKindConfig::Artnet{target, universe, bind} => {
+                let bind = bind.unwrap_or_else(|| artnet_bind.to_owned());
+                check_bind(&bind)?;
+                let universe = universe.unwrap_or(0);
+                check_universes(universe, 1, 0..=artnet::MAX_PORT_ADDRESS)?;
+
+                OutputKind::ArtNet {
+                    bind,
+                    target,
+                    universe,
+                }
+            },
+            OutputKindConfig::Sacn{target, universe, priority, source_name, cid} => {
+                let mut sacn_config = SacnConfig::default();
+
+                if let Some(target) = target {
+                    sacn_config.destination = Destination::Unicast(target);
+                }
+
+                if let Some(universe) = universe {
+                    check_universes(universe, 1, 1..=sacn::MAX_UNIVERSE)?;
+                    sacn_config.universe = universe;
+                }
+
+                if let Some(priority) = priority {
+                    if priority > sacn::MAX_PRIORITY {
+                        return Err(format!("priority {} is over {}", priority, sacn::MAX_PRIORITY));
+                    }
+
+                    sacn_config.priority = priority;
+                }
+
+                if let Some(source_name) = source_name {
+                    sacn_config.source_name = source_name;
+                }
+
+                if let Some(cid) = cid {
+                    sacn_config.cid = Some(sacn::parse_cid(&cid)
+                        .ok_or_else(|| format!("invalid sACN CID {}", cid))?);
+                }
+
+                OutputKind::Sacn(sacn_config)
+            },
+            OutputKindConfig::File{path} => OutputKind::File(path),
+            OutputKindConfig::Null => OutputKind::Null,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum InputKindConfig {
+    Artnet {
+        bind: Option<String>,
+        universe: Option<u16>,
+        universes: Option<u16>,
+    },
+    Sacn {
+        bind: Option<String>,
+        universe: Option<u16>,
+        universes: Option<u16>,
+    },
+}
+
+impl TryFrom<InputKindConfig> for InputKind {
+    type Error = String;
+
+    fn try_from(config: InputKindConfig) -> Result<Self, Self::Error> {
+        let kind = match config {
+            InputKindConfig::Artnet{bind, universe, universes} => InputKind::ArtNet {
+                bind: bind.unwrap_or_else(|| "0.0.0.0:6454".to_owned()),
+                universe: universe.unwrap_or(0),
+                count: universes.unwrap_or(1),
+            },
+            InputKindConfig::Sacn{bind, universe, universes} => InputKind::Sacn {
+                bind: bind.unwrap_or_else(|| "0.0.0.0:5568".to_owned()),
+                universe: universe.unwrap_or(1),
+                count: universes.unwrap_or(1),
+            },
+        };
+
+        match &kind {
+            InputKind::ArtNet{bind, universe, count} => {
+                check_bind(bind)?;
+                check_universes(*universe, *count, 0..=artnet::MAX_PORT_ADDRESS)?;
+            },
+            InputKind::Sacn{bind, universe, count} => {
+                check_bind(bind)?;
+                check_universes(*universe, *count, 1..=sacn::MAX_UNIVERSE)?;
+            },
+        }
+
+        Ok(kind)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct MergeConfig {
+    mode: Option<String>,
+    htp: Option<Vec<String>>,
+    ltp: Option<Vec<String>>,
+    timeout: Option<f32>,
+}
+
+fn parse_merge_mode(mode: &str) -> Result<MergeMode, String> {
+    match mode {
+        "htp" => Ok(MergeMode::Htp),
+        "ltp" => Ok(MergeMode::Ltp),
+        _ => Err(format!("unknown mode {}, expected htp or ltp", mode)),
+    }
+}
+
+fn parse_ranges(ranges: &Option<Vec<String>>, mode: MergeMode)
+        -> Result<Vec<(AddressRange, MergeMode)>, String> {
+    ranges.iter().flatten().map(|range| {
+        let range = range.parse().map_err(|e| format!("invalid channels: {}", e))?;
+        Ok((range, mode))
+    }).collect()
+}
+
+impl MergeConfig {
+    fn rules(&self) -> Result<MergeRules, String> {
+        let mut rules = MergeRules::default();
+
+        if let Some(mode) = &self.mode {
+            rules.mode = parse_merge_mode(mode)?;
+        }
+
+        if let Some(timeout) = self.timeout {
+            rules.timeout = seconds("timeout", timeout)?;
+        }
+
+        rules.overrides = parse_ranges(&self.htp, MergeMode::Htp)?;
+        rules.overrides.append(&mut parse_ranges(&self.ltp, MergeMode::Ltp)?);
+        Ok(rules)
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -26,91 +334,853 @@ struct FixtureDefConfig {
 
 #[derive(Deserialize, Debug)]
 struct ModeConfig {
-    name: String,
+    name: Spanned<String>,
     num_channels: usize,
-    elements: HashMap<String, ElementConfig>
+    elements: HashMap<String, ElementConfig>,
+    defaults: Option<HashMap<String, u8>>,
+    fixed: Option<HashMap<String, u8>>,
+    layout: Option<LayoutConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+enum LayoutConfig {
+    Linear,
+    Grid {
+        columns: usize,
+    },
+    Ring {
+        radius: Option<f32>,
+    },
+}
+
+impl From<LayoutConfig> for Layout {
+    fn from(config: LayoutConfig) -> Self {
+        match config {
+            LayoutConfig::Linear => Layout::Linear,
+            LayoutConfig::Grid{columns} => Layout::Grid { columns },
+            LayoutConfig::Ring{radius} => Layout::Ring { radius },
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 struct ElementConfig {
-    kind: String,
-    i: Option<u8>,
-    r: Option<u8>,
-    g: Option<u8>,
-    b: Option<u8>,
-    uv: Option<u8>,
-}
-
-impl From<ElementConfig> for Element {
-    fn from(config: ElementConfig) -> Self {
-        let kind = match config.kind.as_str() {
-            "intensity" => ElementKind::Intensity(0.0),
-            "rgbiu" => ElementKind::Rgbiu{rgb: Color::black(), uv: 1.0},
-            "rgbi" => ElementKind::Rgbi(Color::black()),
-            "u" => ElementKind::Uv(0.0),
-            "smoke" => ElementKind::Smoke,
-            "gobo" => ElementKind::Gobo,
-            "actuator" => ElementKind::Actuator,
-            _ => ElementKind::Unknown,
-        };
+    kind: Spanned<String>,
+    i: Option<ChannelConfig>,
+    r: Option<ChannelConfig>,
+    g: Option<ChannelConfig>,
+    b: Option<ChannelConfig>,
+    uv: Option<ChannelConfig>,
+    w: Option<ChannelConfig>,
+    a: Option<ChannelConfig>,
+    c: Option<ChannelConfig>,
+    m: Option<ChannelConfig>,
+    y: Option<ChannelConfig>,
+    pan: Option<ChannelConfig>,
+    tilt: Option<ChannelConfig>,
+    pan_range: Option<f32>,
+    tilt_range: Option<f32>,
+    invert_pan: Option<bool>,
+    invert_tilt: Option<bool>,
+    wheel: Option<ChannelConfig>,
+    slots: Option<Vec<SlotConfig>>,
+    scroll_cw: Option<(u8, u8)>,
+    scroll_ccw: Option<(u8, u8)>,
+    rotation: Option<ChannelConfig>,
+    rotation_stop: Option<u8>,
+    rotation_cw: Option<(u8, u8)>,
+    rotation_ccw: Option<(u8, u8)>,
+    params: Option<Vec<ParamConfig>>,
+    max_burst: Option<f32>,
+    cooldown: Option<f32>,
+    curve: Option<CurveConfig>,
+    offset: Option<(f32, f32)>,
+}
+
+/// A named dimmer curve, or a table of DMX outputs for evenly spaced levels
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum CurveConfig {
+    Named(String),
+    Table(Vec<f32>),
+}
+
+impl CurveConfig {
+    fn curve(&self) -> Result<DimmerCurve, String> {
+        match self {
+            CurveConfig::Named(name) => name.parse(),
+            CurveConfig::Table(outputs) => {
+                Ok(DimmerCurve::Table(outputs.iter().map(|output| output / 255.0).collect()))
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ParamConfig {
+    name: String,
+    channel: ChannelConfig,
+    range: Option<(f32, f32)>,
+    dmx: Option<(u8, u8)>,
+    default: Option<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct SlotConfig {
+    name: String,
+    dmx: (u8, u8),
+}
+
+/// A channel number, or `[coarse, fine]` for a 16-bit parameter
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+enum ChannelConfig {
+    Single(u8),
+    Wide(u8, u8),
+}
 
+fn add_channels(element: &mut Element, name: &str, config: &Option<ChannelConfig>) {
+    match config {
+        Some(ChannelConfig::Single(channel)) => element.add_channel(name, *channel),
+        Some(ChannelConfig::Wide(coarse, fine)) => {
+            element.add_channel(name, *coarse);
+            element.add_channel(&format!("{}_fine", name), *fine);
+        },
+        None => {},
+    }
+}
+
+fn element_kind(kind: &str) -> Option<ElementKind> {
+    Some(match kind {
+        "intensity" => ElementKind::Intensity(0.0),
+        "rgbi" | "rgbw" | "rgba" | "rgbaw" | "cmy" => ElementKind::Rgbi{rgb: Color::black(), i: None},
+        "rgbiu" | "rgbwu" | "rgbawu" => ElementKind::Rgbiu{rgb: Color::black(), i: None, uv: 1.0},
+        "u" => ElementKind::Uv(0.0),
+        "smoke" => ElementKind::Smoke(0.0),
+        "gobo" | "color_wheel" => ElementKind::Wheel{slot: 0, rotation: 0.0, scroll: 0.0},
+        "position" => ElementKind::Position{pan: 0.0, tilt: 0.0},
+        "params" => ElementKind::Params(vec![]),
+        _ => return None,
+    })
+}
+
+impl ElementConfig {
+    /// Every channel the element drives, with the name it's given
+    fn channels(&self) -> Vec<(String, u8)> {
+        let mut configs = vec![
+            ("i", &self.i), ("r", &self.r), ("g", &self.g), ("b", &self.b), ("uv", &self.uv),
+            ("w", &self.w), ("a", &self.a), ("c", &self.c), ("m", &self.m), ("y", &self.y),
+            ("pan", &self.pan), ("tilt", &self.tilt), ("wheel", &self.wheel),
+            ("rotation", &self.rotation),
+        ];
+
+        let params: Vec<_> = self.params.iter().flatten()
+            .map(|param| (param.name.as_str(), Some(param.channel.clone())))
+            .collect();
+        configs.extend(params.iter().map(|(name, channel)| (*name, channel)));
+
+        let mut channels = vec![];
+
+        for (name, config) in configs {
+            match config {
+                Some(ChannelConfig::Single(channel)) => channels.push((name.to_owned(), *channel)),
+                Some(ChannelConfig::Wide(coarse, fine)) => {
+                    channels.push((name.to_owned(), *coarse));
+                    channels.push((format!("{}_fine", name), *fine));
+                },
+                None => {},
+            }
+        }
+
+        channels
+    }
+}
+
+impl TryFrom<ElementConfig> for Element {
+    type Error = String;
+
+    fn try_from(config: ElementConfig) -> Result<Self, Self::Error> {
+        let kind = element_kind(config.kind.get_ref()).unwrap_or(ElementKind::Unknown);
         let mut element = Element::new(kind);
+        add_channels(&mut element, "i", &config.i);
+        add_channels(&mut element, "r", &config.r);
+        add_channels(&mut element, "g", &config.g);
+        add_channels(&mut element, "b", &config.b);
+        add_channels(&mut element, "uv", &config.uv);
+        add_channels(&mut element, "w", &config.w);
+        add_channels(&mut element, "a", &config.a);
+        add_channels(&mut element, "c", &config.c);
+        add_channels(&mut element, "m", &config.m);
+        add_channels(&mut element, "y", &config.y);
+        add_channels(&mut element, "pan", &config.pan);
+        add_channels(&mut element, "tilt", &config.tilt);
+        add_channels(&mut element, "wheel", &config.wheel);
+        add_channels(&mut element, "rotation", &config.rotation);
 
-        if let Some(channel) = config.i {
-            element.add_channel("i", channel);
+        let mut range = PositionRange::default();
+
+        if let Some(pan_range) = config.pan_range {
+            range.pan = pan_range;
         }
 
-        if let Some(channel) = config.r {
-            element.add_channel("r", channel);
+        if let Some(tilt_range) = config.tilt_range {
+            range.tilt = tilt_range;
         }
 
-        if let Some(channel) = config.g {
-            element.add_channel("g", channel);
+        range.invert_pan = config.invert_pan.unwrap_or(false);
+        range.invert_tilt = config.invert_tilt.unwrap_or(false);
+        element.set_position_range(range);
+
+        element.set_wheel(Wheel {
+            slots: config.slots.unwrap_or_default().into_iter()
+                .map(|slot| (slot.name, slot.dmx))
+                .collect(),
+            scroll: SpeedRange { stop: 0, cw: config.scroll_cw, ccw: config.scroll_ccw },
+            rotation: SpeedRange {
+                stop: config.rotation_stop.unwrap_or(0),
+                cw: config.rotation_cw,
+                ccw: config.rotation_ccw,
+            },
+        });
+
+        if let Some(curve) = &config.curve {
+            element.set_curve(curve.curve()?);
         }
 
-        if let Some(channel) = config.b {
-            element.add_channel("b", channel);
+        if let Some((x, y)) = config.offset {
+            element.set_offset(x, y);
         }
 
-        if let Some(channel) = config.uv {
-            element.add_channel("uv", channel);
+        element.set_smoke_timing(SmokeTiming {
+            max_burst: config.max_burst.map(|max_burst| seconds("max_burst", max_burst)).transpose()?,
+            cooldown: seconds("cooldown", config.cooldown.unwrap_or(0.0))?,
+        });
+
+        let params = config.params.unwrap_or_default().into_iter().map(|param| {
+            add_channels(&mut element, &param.name, &Some(param.channel));
+            let range = param.range.unwrap_or((0.0, 255.0));
+
+            Param {
+                name: param.name,
+                range,
+                dmx: param.dmx.unwrap_or((0, 255)),
+                default: param.default.unwrap_or(range.0),
+            }
+        }).collect();
+
+        element.set_params(params);
+
+        if let ElementKind::Params(_) = element.kind() {
+            element.set_kind(ElementKind::Params(element.param_defaults()));
         }
 
-        element
+        Ok(element)
     }
 }
 
-fn load_elements(kind: &str, mode: &str) -> (HashMap<String, Element>, usize) {
-    let fixture_path = "fixtures/".to_owned() + kind + ".toml";
-    let mut config: FixtureDefConfig = toml::from_str(&read_to_string(fixture_path).unwrap()).unwrap();
+fn channel_values(mode: &ModeConfig, values: &Option<HashMap<String, u8>>) -> Result<Vec<(usize, u8)>, String> {
+    let mut values = values.iter().flatten().map(|(channel, value)| {
+        match channel.parse() {
+            Ok(channel) if channel >= 1 && channel <= mode.num_channels => Ok((channel, *value)),
+            _ => Err(format!("Mode {} has no channel {}", mode.name.get_ref(), channel)),
+        }
+    }).collect::<Result<Vec<_>, _>>()?;
 
-    config.modes.retain(|mode_config| mode_config.name == mode);
+    values.sort();
+    Ok(values)
+}
 
-    if !config.modes.is_empty() {
-        let elements = config.modes[0].elements.clone();
-        (elements.into_iter().map(|(name, config)| {
-            (name, config.into())
-        }).collect(), config.modes[0].num_channels)
-    } else {
-        (HashMap::new(), 0)
-    }
+/// A fixture mode's elements and what's needed to patch them
+struct LoadedMode {
+    elements: HashMap<String, Element>,
+    num_channels: usize,
+    channel_values: ChannelValues,
+    layout: Layout,
 }
 
-pub fn build_from_config(config_file: &str) -> Installation {
-    let config_text = read_to_string(config_file)
-                        .unwrap_or_else(|_| panic!("Failed to find {}", config_file));
-    let config: InstallationConfig = toml::from_str(&config_text)
-                        .unwrap_or_else(|_| panic!("Failed to parse {}", config_file));
+fn load_elements(library: &Library, kind: &str, mode: &str) -> Result<LoadedMode, String> {
+    let fixture_path = library.find_fixture(kind)?;
+    let text = read_to_string(&fixture_path)
+                .map_err(|e| format!("Failed to read {}: {}", fixture_path.display(), e))?;
+    let config: FixtureDefConfig = toml::from_str(&text)
+                .map_err(|e| format!("Failed to parse {}: {}", fixture_path.display(), e))?;
 
-    let fixtures: HashMap<_, _> = config.fixtures.into_iter().map(|(name, config)| {
-        let (elements, num_channels) = load_elements(&config.kind, &config.mode);
-        let fixture = Fixture::new(
-            elements, Point2::new(config.pos.0, config.pos.1),
-            config.channel as usize, num_channels
+    let mode = config.modes.into_iter()
+                .find(|mode_config| mode_config.name.get_ref() == mode)
+                .ok_or_else(|| format!("{} has no mode {}", kind, mode))?;
+
+    load_mode(mode)
+}
+
+fn load_mode(mode: ModeConfig) -> Result<LoadedMode, String> {
+    let defaults = channel_values(&mode, &mode.defaults)?;
+    let fixed = channel_values(&mode, &mode.fixed)?;
+
+    let elements = mode.elements.into_iter().map(|(name, config)| {
+        let element = Element::try_from(config).map_err(|e| format!("Element {}: {}", name, e))?;
+        Ok((name, element))
+    }).collect::<Result<_, String>>()?;
+
+    Ok(LoadedMode {
+        elements,
+        num_channels: mode.num_channels,
+        channel_values: ChannelValues { defaults, fixed },
+        layout: mode.layout.map(Layout::from).unwrap_or_default(),
+    })
+}
+
+/// Checks a fixture definition that isn't in a library, then loads each of
+/// its modes as an installation would, returning their elements by mode.
+#[cfg(test)]
+pub fn load_fixture_def(path: &str, text: &str)
+        -> Result<HashMap<String, HashMap<String, Element>>, Vec<Diagnostic>> {
+    let mut diagnostics = vec![];
+
+    let config = match check_fixture_def(path, text, &mut diagnostics) {
+        Some(config) if diagnostics.is_empty() => config,
+        _ => return Err(diagnostics),
+    };
+
+    config.modes.into_iter().map(|mode| {
+        let name = mode.name.get_ref().clone();
+        match load_mode(mode) {
+            Ok(loaded) => Ok((name, loaded.elements)),
+            Err(e) => Err(vec![Diagnostic::new(path, None, &format!("Mode {}: {}", name, e))]),
+        }
+    }).collect()
+}
+
+pub fn build_from_config(config_file: &str, library: &Library) -> Result<Installation, Diagnostic> {
+    let (text, config) = check::read_toml::<InstallationConfig>(config_file)?;
+
+    let mut patches = vec![];
+    let mut problems = vec![];
+
+    let fixtures: HashMap<_, _> = config.fixtures.into_iter().filter_map(|(name, config)| {
+        let at = config.kind.start();
+        let mut problem = |e: String| {
+            problems.push(Diagnostic::at(config_file, &text, at, &format!("Fixture {}: {}", name, e)));
+        };
+
+        let address = match config.address.parse() {
+            Ok(address) => address,
+            Err(e) => {
+                problem(e);
+                return None;
+            }
+        };
+
+        let mode = match load_elements(library, config.kind.get_ref(), config.mode.get_ref()) {
+            Ok(mode) => mode,
+            Err(e) => {
+                problem(e);
+                return None;
+            }
+        };
+
+        let curve = match config.curve.as_ref().map(CurveConfig::curve).transpose() {
+            Ok(curve) => curve,
+            Err(e) => {
+                problem(e);
+                return None;
+            }
+        };
+
+        let num_channels = mode.num_channels;
+        let mut fixture = Fixture::new(
+            mode.elements, Point2::new(config.pos.0, config.pos.1),
+            address, num_channels
         );
+        fixture.set_channel_values(mode.channel_values);
+        fixture.set_layout(mode.layout);
+        fixture.set_placement(config.placement());
 
-        (name, fixture)
+        if let Some(curve) = curve {
+            fixture.set_curve(curve);
+        }
+
+        patches.push((name.clone(), address, num_channels));
+        Some((name, fixture))
     }).collect();
 
-    Installation::new(fixtures)
+    for problem in patch::check_patches(&patches) {
+        problems.push(Diagnostic::new(config_file, None, &problem));
+    }
+
+    check::first_problem(problems)?;
+    Ok(Installation::new(fixtures))
+}
+
+/// Checks a fixture definition's modes, returning it if it could be parsed.
+fn check_fixture_def(path: &str, text: &str, diagnostics: &mut Vec<Diagnostic>) -> Option<FixtureDefConfig> {
+    let config: FixtureDefConfig = match toml::from_str(text) {
+        Ok(config) => config,
+        Err(e) => {
+            diagnostics.push(Diagnostic::from_toml(path, &e));
+            return None;
+        }
+    };
+
+    for mode in config.modes.iter() {
+        let mode_name = mode.name.get_ref();
+        let mut elements: Vec<_> = mode.elements.iter().collect();
+        elements.sort_by_key(|(_, element)| element.kind.start());
+
+        // Channel number to the element and channel name that drive it
+        let mut used: HashMap<u8, (&str, String)> = HashMap::new();
+
+        for (name, element) in elements {
+            let at = element.kind.start();
+
+            if element_kind(element.kind.get_ref()).is_none() {
+                diagnostics.push(Diagnostic::at(path, text, at, &format!(
+                    "Element {} of mode {} has unknown kind {}",
+                    name, mode_name, element.kind.get_ref())));
+            }
+
+            if let Some(Err(e)) = element.curve.as_ref().map(CurveConfig::curve) {
+                diagnostics.push(Diagnostic::at(path, text, at, &format!(
+                    "Element {} of mode {}: {}", name, mode_name, e)));
+            }
+
+            for (setting, value) in [("max_burst", element.max_burst), ("cooldown", element.cooldown)].iter() {
+                if let Some(Err(e)) = value.map(|value| seconds(setting, value)) {
+                    diagnostics.push(Diagnostic::at(path, text, at, &format!(
+                        "Element {} of mode {}: {}", name, mode_name, e)));
+                }
+            }
+
+            for (channel_name, channel) in element.channels() {
+                if channel == 0 || channel as usize > mode.num_channels {
+                    diagnostics.push(Diagnostic::at(path, text, at, &format!(
+                        "Element {} {} is on channel {}, mode {} has channels 1-{}",
+                        name, channel_name, channel, mode_name, mode.num_channels)));
+                } else if let Some((other, other_channel)) = used.get(&channel) {
+                    diagnostics.push(Diagnostic::at(path, text, at, &format!(
+                        "Element {} {} is on channel {}, already used by element {} {}",
+                        name, channel_name, channel, other, other_channel)));
+                } else {
+                    used.insert(channel, (name, channel_name));
+                }
+            }
+        }
+
+        if let Some(LayoutConfig::Grid{columns: 0}) = mode.layout {
+            diagnostics.push(Diagnostic::at(path, text, mode.name.start(), &format!(
+                "Mode {} has a grid layout with no columns", mode_name)));
+        }
+
+        for values in [&mode.defaults, &mode.fixed].iter() {
+            if let Err(e) = channel_values(mode, values) {
+                diagnostics.push(Diagnostic::at(path, text, mode.name.start(), &e));
+            }
+        }
+    }
+
+    Some(config)
+}
+
+/// A fixture's element names, each with the names of its wheel slots
+pub type ElementSlots = HashMap<String, Vec<String>>;
+
+/// Checks an installation and the fixture definitions it uses, returning each
+/// fixture with its elements, if its definition could be loaded.
+pub fn check_config(config_file: &str, library: &Library, diagnostics: &mut Vec<Diagnostic>)
+        -> Option<HashMap<String, Option<ElementSlots>>> {
+    let (text, config) = match check::read_toml::<InstallationConfig>(config_file) {
+        Ok(config) => config,
+        Err(diagnostic) => {
+            diagnostics.push(diagnostic);
+            return None;
+        }
+    };
+
+    let mut configs: Vec<_> = config.fixtures.iter().collect();
+    configs.sort_by_key(|(_, fixture)| fixture.kind.start());
+
+    // Each fixture definition is checked once, however many fixtures use it
+    let mut defs: HashMap<&str, Result<Option<FixtureDefConfig>, String>> = HashMap::new();
+    let mut fixtures = HashMap::new();
+    let mut patches = vec![];
+
+    for (name, fixture) in configs {
+        fixtures.insert(name.clone(), None);
+        let kind = fixture.kind.get_ref();
+        let at = fixture.kind.start();
+
+        let def = defs.entry(kind).or_insert_with(|| {
+            let path = library.find_fixture(kind)?;
+            let def_text = read_to_string(&path)
+                            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            Ok(check_fixture_def(&path.display().to_string(), &def_text, diagnostics))
+        });
+
+        let def = match def {
+            Ok(Some(def)) => def,
+            Ok(None) => continue,
+            Err(e) => {
+                diagnostics.push(Diagnostic::at(config_file, &text, at, &format!("Fixture {}: {}", name, e)));
+                continue;
+            }
+        };
+
+        if let Some(Err(e)) = fixture.curve.as_ref().map(CurveConfig::curve) {
+            diagnostics.push(Diagnostic::at(config_file, &text, at, &format!("Fixture {}: {}", name, e)));
+        }
+
+        if matches!(fixture.scale, Some(scale) if scale <= 0.0) {
+            diagnostics.push(Diagnostic::at(config_file, &text, at, &format!(
+                "Fixture {}: scale must be more than 0", name)));
+        }
+
+        let mode = match def.modes.iter().find(|mode| mode.name.get_ref() == fixture.mode.get_ref()) {
+            Some(mode) => mode,
+            None => {
+                let modes: Vec<_> = def.modes.iter().map(|mode| mode.name.get_ref().as_str()).collect();
+                diagnostics.push(Diagnostic::at(config_file, &text, fixture.mode.start(), &format!(
+                    "Fixture {}: {} has no mode {}, expected one of {}",
+                    name, kind, fixture.mode.get_ref(), modes.join(", "))));
+                continue;
+            }
+        };
+
+        match fixture.address.parse() {
+            Ok(address) => patches.push((name.clone(), address, mode.num_channels)),
+            Err(e) => {
+                diagnostics.push(Diagnostic::at(config_file, &text, at, &format!("Fixture {}: {}", name, e)));
+            }
+        }
+
+        fixtures.insert(name.clone(), Some(mode.elements.iter().map(|(element, config)| {
+            let slots = config.slots.iter().flatten().map(|slot| slot.name.clone()).collect();
+            (element.clone(), slots)
+        }).collect()));
+    }
+
+    for problem in patch::check_patches(&patches) {
+        diagnostics.push(Diagnostic::new(config_file, None, &problem));
+    }
+
+    let outputs = outputs(&config, config_file, &text, diagnostics);
+    let inputs = inputs(&config, config_file, &text, diagnostics);
+    check_binds(&outputs, &inputs, config_file, &text, diagnostics);
+    check_output_universes(&outputs, &patches, config_file, &text, diagnostics);
+    merge_rules(&config, config_file, &text, diagnostics);
+
+    Some(fixtures)
+}
+
+/// The installation's outputs, adding a diagnostic for any that can't be used.
+fn outputs(config: &InstallationConfig, file: &str, text: &str,
+           diagnostics: &mut Vec<Diagnostic>) -> Vec<OutputConfig> {
+    let outputs = match &config.outputs {
+        Some(outputs) => outputs,
+        None => {
+            // Installations from before outputs were configurable used the
+            // serial dongle
+            return vec![OutputConfig::new("serial", OutputKind::Serial {
+                device: "/dev/ttyUSB0".to_owned(),
+                universe: 1,
+                break_us: 136,
+            })];
+        }
+    };
+
+    // Outputs answer ArtPoll on the Art-Net port, unless an input needs it to
+    // receive
+    let artnet_input = config.inputs.iter().flatten()
+        .any(|(_, input)| matches!(input, InputKindConfig::Artnet{..}));
+    let artnet_bind = if artnet_input { "0.0.0.0:0" } else { "0.0.0.0:6454" };
+
+    let mut names: Vec<_> = outputs.keys().collect();
+    names.sort();
+
+    names.into_iter().filter_map(|name| match outputs[name].output(name, artnet_bind) {
+        Ok(output) => Some(output),
+        Err(e) => {
+            diagnostics.push(Diagnostic::in_table(file, text, &format!("outputs.{}", name),
+                                                  &format!("Output {}: {}", name, e)));
+            None
+        }
+    }).collect()
+}
+
+/// The installation's inputs, adding a diagnostic for any that can't be used.
+fn inputs(config: &InstallationConfig, file: &str, text: &str,
+          diagnostics: &mut Vec<Diagnostic>) -> Vec<InputConfig> {
+    let inputs = match &config.inputs {
+        Some(inputs) => inputs,
+        None => return vec![],
+    };
+
+    let mut names: Vec<_> = inputs.keys().collect();
+    names.sort();
+
+    names.into_iter().filter_map(|name| match InputKind::try_from(inputs[name].clone()) {
+        Ok(kind) => Some(InputConfig::new(name, kind)),
+        Err(e) => {
+            diagnostics.push(Diagnostic::in_table(file, text, &format!("inputs.{}", name),
+                                                  &format!("Input {}: {}", name, e)));
+            None
+        }
+    }).collect()
+}
+
+/// Adds a diagnostic for each output or input that binds a port an earlier
+/// one already has, since only one of them could open.
+fn check_binds(outputs: &[OutputConfig], inputs: &[InputConfig], file: &str, text: &str,
+               diagnostics: &mut Vec<Diagnostic>) {
+    let output_binds = outputs.iter().filter_map(|output| match &output.kind {
+        OutputKind::ArtNet{bind, ..} => {
+            Some((format!("outputs.{}", output.name), format!("Output {}", output.name), bind))
+        },
+        _ => None,
+    });
+    let input_binds = inputs.iter().map(|input| match &input.kind {
+        InputKind::ArtNet{bind, ..} | InputKind::Sacn{bind, ..} => {
+            (format!("inputs.{}", input.name), format!("Input {}", input.name), bind)
+        },
+    });
+
+    let mut bound: Vec<(String, SocketAddr)> = vec![];
+
+    for (table, user, bind) in output_binds.chain(input_binds) {
+        let addr = match bind.parse::<SocketAddr>() {
+            Ok(addr) => addr,
+            Err(_) => continue,
+        };
+
+        // Port 0 is any free port
+        let clash = bound.iter().find(|(_, other)| {
+            addr.port() != 0 && other.port() == addr.port()
+                && (other.ip() == addr.ip() || other.ip().is_unspecified() || addr.ip().is_unspecified())
+        });
+
+        if let Some((other, _)) = clash {
+            diagnostics.push(Diagnostic::in_table(file, text, &table, &format!(
+                "{}: bind {} is already used by {}", user, bind, other.to_lowercase())));
+        }
+
+        bound.push((user, addr));
+    }
+}
+
+/// Adds a diagnostic for each output that would send a patched universe past
+/// the last its protocol has.
+fn check_output_universes(outputs: &[OutputConfig], patches: &[(String, Address, usize)], file: &str,
+                          text: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let last_patched = match patches.iter().map(|(_, address, _)| address.universe).max() {
+        Some(universe) => universe,
+        None => return,
+    };
+
+    for output in outputs {
+        let (first, range) = match &output.kind {
+            OutputKind::ArtNet{universe, ..} => (*universe, 0..=artnet::MAX_PORT_ADDRESS),
+            OutputKind::Sacn(config) => (config.universe, 1..=sacn::MAX_UNIVERSE),
+            _ => continue,
+        };
+
+        let last_sent = first as u32 + last_patched as u32 - 1;
+
+        if last_sent > *range.end() as u32 {
+            diagnostics.push(Diagnostic::in_table(file, text, &format!("outputs.{}", output.name), &format!(
+                "Output {}: patch universe {} would be sent as universe {}, past the last, {}",
+                output.name, last_patched, last_sent, range.end())));
+        }
+    }
+}
+
+fn merge_rules(config: &InstallationConfig, file: &str, text: &str,
+               diagnostics: &mut Vec<Diagnostic>) -> MergeRules {
+    match config.merge.as_ref().map(MergeConfig::rules) {
+        Some(Ok(rules)) => rules,
+        Some(Err(e)) => {
+            diagnostics.push(Diagnostic::in_table(file, text, "merge", &format!("Merge: {}", e)));
+            MergeRules::default()
+        },
+        None => MergeRules::default(),
+    }
+}
+
+pub fn build_outputs_from_config(config_file: &str) -> Result<Vec<OutputConfig>, Diagnostic> {
+    let (text, config) = check::read_toml::<InstallationConfig>(config_file)?;
+    let mut problems = vec![];
+    let outputs = outputs(&config, config_file, &text, &mut problems);
+    check::first_problem(problems)?;
+    Ok(outputs)
+}
+
+pub fn build_inputs_from_config(config_file: &str) -> Result<Vec<InputConfig>, Diagnostic> {
+    let (text, config) = check::read_toml::<InstallationConfig>(config_file)?;
+    let mut problems = vec![];
+    let inputs = inputs(&config, config_file, &text, &mut problems);
+    check::first_problem(problems)?;
+    Ok(inputs)
+}
+
+pub fn build_merge_rules_from_config(config_file: &str) -> Result<MergeRules, Diagnostic> {
+    let (text, config) = check::read_toml::<InstallationConfig>(config_file)?;
+    let mut problems = vec![];
+    let rules = merge_rules(&config, config_file, &text, &mut problems);
+    check::first_problem(problems)?;
+    Ok(rules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_fixture_def() {
+        let text = r#"
+[[modes]]
+name = "4ch"
+num_channels = 4
+defaults = {5 = 255}
+elements.1 = {kind = "rgbi", r = 1, g = 2, b = 3, cooldown = -5.0}
+elements.2 = {kind = "rgbx", i = 3}
+elements.3 = {kind = "params", params = [{name = "zoom", channel = [4, 5]}]}
+"#;
+
+        let mut diagnostics = vec![];
+        assert!(check_fixture_def("fixtures/par.toml", text, &mut diagnostics).is_some());
+
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+        assert_eq!(vec![
+            (Some(6), "Element 1 of mode 4ch: cooldown should be a number of seconds, not -5"),
+            (Some(7), "Element 2 of mode 4ch has unknown kind rgbx"),
+            (Some(7), "Element 2 i is on channel 3, already used by element 1 b"),
+            (Some(8), "Element 3 zoom_fine is on channel 5, mode 4ch has channels 1-4"),
+            (Some(3), "Mode 4ch has no channel 5"),
+        ], lines);
+
+        let mut diagnostics = vec![];
+        assert!(check_fixture_def("fixtures/par.toml", "[[modes]]\nname = 4", &mut diagnostics).is_none());
+        assert_eq!(Some(2), diagnostics[0].line);
+    }
+
+    #[test]
+    fn test_check_outputs_inputs_and_merge() {
+        let text = r#"[fixtures]
+
+[outputs.network]
+kind = "sacn"
+cid = "xyz"
+
+[outputs.node]
+kind = "artnet"
+target = "10.0.0.1"
+bind = "anywhere"
+
+[outputs.rehearsal]
+kind = "null"
+keep_alive = -1
+
+[inputs.console]
+kind = "sacn"
+bind = "0.0.0.0"
+
+[merge]
+timeout = nan
+"#;
+
+        let config: InstallationConfig = toml::from_str(text).unwrap();
+        let mut diagnostics = vec![];
+        assert!(outputs(&config, "i.toml", text, &mut diagnostics).is_empty());
+        assert!(inputs(&config, "i.toml", text, &mut diagnostics).is_empty());
+        merge_rules(&config, "i.toml", text, &mut diagnostics);
+
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+        assert_eq!(vec![
+            (Some(3), "Output network: invalid sACN CID xyz"),
+            (Some(7), "Output node: bind anywhere should be an address and port, such as 0.0.0.0:6454"),
+            (Some(12), "Output rehearsal: keep_alive should be a number of seconds, not -1"),
+            (Some(16), "Input console: bind 0.0.0.0 should be an address and port, such as 0.0.0.0:6454"),
+            (Some(20), "Merge: timeout should be a number of seconds, not NaN"),
+        ], lines);
+
+        let merge: MergeConfig = toml::from_str("mode = \"newest\"").unwrap();
+        assert_eq!(Some("unknown mode newest, expected htp or ltp".to_owned()), merge.rules().err());
+    }
+
+    #[test]
+    fn test_output_rate() {
+        let rate = |text| toml::from_str::<OutputEntryConfig>(text).unwrap().timing().map(|t| t.rate);
+        assert_eq!(Ok(100.0), rate("kind = \"null\"\nrate = 100"));
+        assert_eq!(Err("rate should be a number of frames per second up to 1000, not 0".to_owned()),
+                   rate("kind = \"null\"\nrate = 0"));
+        assert!(rate("kind = \"null\"\nrate = -1").is_err());
+        assert!(rate("kind = \"null\"\nrate = inf").is_err());
+    }
+
+    #[test]
+    fn test_bind_clash() {
+        let text = r#"[fixtures]
+
+[outputs.node]
+kind = "artnet"
+target = "10.0.0.1"
+
+[inputs.console]
+kind = "artnet"
+"#;
+
+        // Outputs leave the Art-Net port to inputs
+        let config: InstallationConfig = toml::from_str(text).unwrap();
+        let mut diagnostics = vec![];
+        let built_outputs = outputs(&config, "i.toml", text, &mut diagnostics);
+        let built_inputs = inputs(&config, "i.toml", text, &mut diagnostics);
+        check_binds(&built_outputs, &built_inputs, "i.toml", text, &mut diagnostics);
+        assert!(matches!(&built_outputs[0].kind, OutputKind::ArtNet{bind, ..} if bind == "0.0.0.0:0"));
+        assert!(diagnostics.is_empty());
+
+        let text = text.replace("target = \"10.0.0.1\"", "target = \"10.0.0.1\"\nbind = \"0.0.0.0:6454\"")
+            + "\n[inputs.desk]\nkind = \"artnet\"\nbind = \"192.168.1.10:6454\"\n";
+        let config: InstallationConfig = toml::from_str(&text).unwrap();
+        let built_outputs = outputs(&config, "i.toml", &text, &mut diagnostics);
+        let built_inputs = inputs(&config, "i.toml", &text, &mut diagnostics);
+        check_binds(&built_outputs, &built_inputs, "i.toml", &text, &mut diagnostics);
+
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+        assert_eq!(vec![
+            (Some(8), "Input console: bind 0.0.0.0:6454 is already used by output node"),
+            (Some(11), "Input desk: bind 192.168.1.10:6454 is already used by output node"),
+        ], lines);
+    }
+
+    #[test]
+    fn test_universe_ranges() {
+        let text = r#"[fixtures]
+
+[outputs.network]
+kind = "sacn"
+universe = 63999
+
+[inputs.console]
+kind = "sacn"
+universe = 63990
+universes = 20
+"#;
+
+        let config: InstallationConfig = toml::from_str(text).unwrap();
+        let mut diagnostics = vec![];
+        let built_outputs = outputs(&config, "i.toml", text, &mut diagnostics);
+        inputs(&config, "i.toml", text, &mut diagnostics);
+        let patches = vec![("par".to_owned(), Address::new(2, 1), 8)];
+        check_output_universes(&built_outputs, &patches, "i.toml", text, &mut diagnostics);
+
+        let lines: Vec<_> = diagnostics.iter().map(|d| (d.line, d.message.as_str())).collect();
+        assert_eq!(vec![
+            (Some(7), "Input console: universes 63990 to 64009 should be within 1 to 63999"),
+            (Some(3), "Output network: patch universe 2 would be sent as universe 64000, past the last, 63999"),
+        ], lines);
+
+        let output: OutputKindConfig = toml::from_str("kind = \"sacn\"\nuniverse = 0").unwrap();
+        assert_eq!(Some("universe 0 should be 1 to 63999".to_owned()), output.into_kind("").err());
+        let output: OutputKindConfig = toml::from_str("kind = \"artnet\"\ntarget = \"node\"\nuniverse = 32768")
+            .unwrap();
+        assert_eq!(Some("universe 32768 should be 0 to 32767".to_owned()), output.into_kind("0.0.0.0:0").err());
+    }
 }
@@ -0,0 +1,101 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
+const SYSTEM_DIR: &str = "/usr/share/lightboard";
+
+/// Where fixture definitions and pattern scripts are looked for, in order of
+/// preference, so a show can use a shared library and be run from anywhere.
+#[derive(Debug, Clone)]
+pub struct Library {
+    fixture_dirs: Vec<PathBuf>,
+    pattern_dirs: Vec<PathBuf>,
+}
+
+/// The user's own library, `$XDG_DATA_HOME/lightboard` or
+/// `~/.local/share/lightboard`.
+fn user_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(data) if !data.is_empty() => Some(PathBuf::from(data).join("lightboard")),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/lightboard")),
+    }
+}
+
+/// A configured path, relative to the show's directory unless it's absolute
+/// or starts with `~/`.
+fn resolve(base: &Path, path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/") {
+        if let Some(home) = env::var_os("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+
+    base.join(path)
+}
+
+fn search_dirs(base: &Path, configured: &[String], subdir: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![base.join(subdir)];
+    dirs.extend(configured.iter().map(|path| resolve(base, path)));
+    dirs.extend(user_dir().map(|dir| dir.join(subdir)));
+    dirs.push(Path::new(SYSTEM_DIR).join(subdir));
+    dirs
+}
+
+fn find(dirs: &[PathBuf], file_name: &str) -> Result<PathBuf, String> {
+    dirs.iter().map(|dir| dir.join(file_name)).find(|path| path.is_file()).ok_or_else(|| {
+        let dirs: Vec<_> = dirs.iter().map(|dir| dir.display().to_string()).collect();
+        format!("No {} in {}", file_name, dirs.join(", "))
+    })
+}
+
+impl Library {
+    /// Searches the `fixtures` and `patterns` directories beside the show,
+    /// then the configured directories, then the user's and system libraries.
+    pub fn new(show_dir: &Path, fixture_dirs: &[String], pattern_dirs: &[String]) -> Self {
+        Self {
+            fixture_dirs: search_dirs(show_dir, fixture_dirs, "fixtures"),
+            pattern_dirs: search_dirs(show_dir, pattern_dirs, "patterns"),
+        }
+    }
+
+    pub fn pattern_dirs(&self) -> &[PathBuf] {
+        &self.pattern_dirs
+    }
+
+    /// The first definition of a fixture kind, e.g. `generic_spot` for
+    /// `generic_spot.toml`.
+    pub fn find_fixture(&self, kind: &str) -> Result<PathBuf, String> {
+        find(&self.fixture_dirs, &format!("{}.toml", kind))
+    }
+
+    pub fn find_pattern(&self, script_name: &str) -> Result<PathBuf, String> {
+        find(&self.pattern_dirs, script_name)
+    }
+}
+
+impl Default for Library {
+    /// The library for a show in the working directory.
+    fn default() -> Self {
+        Library::new(Path::new(""), &[], &[])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_order() {
+        let library = Library::new(Path::new("tests/golden"), &["../../fixtures".to_owned()], &[]);
+        assert_eq!(Path::new("tests/golden/fixtures"), library.fixture_dirs[0]);
+        assert_eq!(Ok(PathBuf::from("tests/golden/../../fixtures/generic_spot.toml")),
+                   library.find_fixture("generic_spot"));
+
+        assert_eq!(Ok(PathBuf::from("fixtures/generic_spot.toml")),
+                   Library::default().find_fixture("generic_spot"));
+        assert_eq!(Ok(PathBuf::from("patterns/sweep.lua")),
+                   Library::default().find_pattern("sweep.lua"));
+
+        let missing = Library::default().find_fixture("no_such_fixture").unwrap_err();
+        assert!(missing.starts_with("No no_such_fixture.toml in fixtures, "));
+    }
+}
@@ -1,3 +1,5 @@
+use std::str::FromStr;
+
 #[derive(Debug, Clone)]
 pub struct Color {
     r: f32,
@@ -72,6 +74,60 @@ impl From<(f32, f32, f32)> for Color {
     }
 }
 
+/// How a level maps to the output sent to a dimmer or LED driver.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum DimmerCurve {
+    #[default]
+    Linear,
+    /// The level squared, for finer control at low levels
+    Square,
+    /// Eases in and out at both ends of the range
+    SCurve,
+    /// The light from a tungsten lamp on a linear dimmer, so LEDs can match
+    /// incandescent fixtures at the same level
+    Incandescent,
+    /// Outputs for evenly spaced levels from 0 to 1, interpolated between
+    Table(Vec<f32>),
+}
+
+impl DimmerCurve {
+    pub fn apply(&self, level: Intensity) -> Intensity {
+        let level = clamp(level);
+
+        match self {
+            DimmerCurve::Linear => level,
+            DimmerCurve::Square => level * level,
+            DimmerCurve::SCurve => level * level * (3.0 - 2.0 * level),
+            // Light output goes roughly as the voltage to the power of 3.4
+            DimmerCurve::Incandescent => level.powf(3.4),
+            DimmerCurve::Table(outputs) => {
+                if outputs.len() < 2 {
+                    return outputs.first().map_or(level, |output| clamp(*output));
+                }
+
+                let position = level * (outputs.len() - 1) as f32;
+                let i = (position as usize).min(outputs.len() - 2);
+                let along = position - i as f32;
+                clamp(outputs[i] + (outputs[i + 1] - outputs[i]) * along)
+            },
+        }
+    }
+}
+
+impl FromStr for DimmerCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "linear" => Ok(DimmerCurve::Linear),
+            "square" => Ok(DimmerCurve::Square),
+            "s_curve" => Ok(DimmerCurve::SCurve),
+            "incandescent" => Ok(DimmerCurve::Incandescent),
+            _ => Err(format!("Unknown dimmer curve {}", s)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::f32::NAN;
@@ -93,6 +149,24 @@ mod tests {
         assert_eq!(0.5019608, c.b);
     }
 
+    #[test]
+    fn test_dimmer_curves() {
+        assert_eq!(0.25, DimmerCurve::Linear.apply(0.25));
+        assert_eq!(1.0, DimmerCurve::Linear.apply(1.5));
+        assert_eq!(0.25, DimmerCurve::Square.apply(0.5));
+        assert_eq!(0.5, DimmerCurve::SCurve.apply(0.5));
+        assert!(DimmerCurve::Incandescent.apply(0.5) < 0.1);
+        assert_eq!(1.0, DimmerCurve::Incandescent.apply(1.0));
+
+        let table = DimmerCurve::Table(vec![0.0, 0.1, 1.0]);
+        assert_eq!(0.05, table.apply(0.25));
+        assert_eq!(0.55, table.apply(0.75));
+        assert_eq!(1.0, table.apply(1.0));
+
+        assert_eq!(Ok(DimmerCurve::SCurve), "s_curve".parse());
+        assert!("cubic".parse::<DimmerCurve>().is_err());
+    }
+
     #[test]
     fn test_adding_colors() {
         let c0 = Color::new(0.1, 0.2, 0.3);
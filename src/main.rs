@@ -2,6 +2,19 @@
 extern crate lazy_static;
 
 mod dmx_control;
+mod artnet;
+mod sacn;
+mod patch;
+mod output;
+mod recording;
+mod engine;
+mod dmx_input;
+mod merge;
+mod park;
+mod ofl;
+mod check;
+mod library;
+mod headless;
 mod gui;
 mod imgui_wrapper;
 mod installation;
@@ -10,6 +23,7 @@ mod effect;
 mod cue;
 mod hitbox;
 mod pattern;
+mod pixel_map;
 mod light;
 mod installation_loader;
 mod show_loader;
@@ -18,28 +32,185 @@ mod command_input_parser;
 
 use std::{thread};
 use std::sync::mpsc;
-use clap::{Arg, App};
+use std::net::{IpAddr, SocketAddr};
+use clap::{Arg, App, SubCommand};
 use installation::Installation;
 use effect::EffectPool;
 use cue::CueList;
+use output::OutputConfig;
+use recording::Recording;
+use engine::Engine;
+use dmx_input::InputConfig;
+use merge::{Merger, MergeRules};
 
 fn main() {
     let matches = App::new("Lightboard-rs")
                     .about("Rust DMX lighting controller")
                     .arg(Arg::with_name("show")
                             .help("Name of show file"))
+                    .arg(Arg::with_name("output")
+                            .long("output")
+                            .short("o")
+                            .takes_value(true)
+                            .multiple(true)
+                            .help("Only use these outputs from the installation (default all)"))
+                    .arg(Arg::with_name("play")
+                            .long("play")
+                            .takes_value(true)
+                            .help("Play a recording through the outputs instead of running the show"))
+                    .arg(Arg::with_name("loop")
+                            .long("loop")
+                            .requires("play")
+                            .help("Repeat the recording until stopped"))
+                    .arg(Arg::with_name("headless")
+                            .long("headless")
+                            .help("Run without a window, taking commands from stdin"))
+                    .arg(Arg::with_name("command-port")
+                            .long("command-port")
+                            .takes_value(true)
+                            .requires("headless")
+                            .validator(|port| port.parse::<u16>().map(|_| ())
+                                                .map_err(|_| format!("{} isn't a port number", port)))
+                            .help("Also take commands from TCP connections on this port"))
+                    .arg(Arg::with_name("command-bind")
+                            .long("command-bind")
+                            .takes_value(true)
+                            .requires("command-port")
+                            .validator(|ip| ip.parse::<IpAddr>().map(|_| ())
+                                                .map_err(|_| format!("{} isn't an IP address", ip)))
+                            .help("Address to take command connections on (default 127.0.0.1, \
+                                   so only from this computer)"))
+                    .subcommand(SubCommand::with_name("import-ofl")
+                            .about("Convert an Open Fixture Library fixture file into a fixture definition")
+                            .arg(Arg::with_name("file")
+                                    .required(true)
+                                    .help("Open Fixture Library fixture JSON file"))
+                            .arg(Arg::with_name("to")
+                                    .help("Fixture definition to write (default fixtures/<name>.toml)")))
+                    .subcommand(SubCommand::with_name("check")
+                            .about("Check a show, its installation and fixture definitions for problems")
+                            .arg(Arg::with_name("show")
+                                    .help("Name of show file")))
                     .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("import-ofl") {
+        let path = matches.value_of("file").unwrap();
+        let conversion = ofl::import(path, matches.value_of("to")).unwrap_or_else(|e| {
+            println!("Failed to import {}: {}", path, e);
+            std::process::exit(1);
+        });
+
+        for problem in conversion.problems.iter() {
+            println!("{}", problem);
+        }
+
+        return;
+    }
+
+    if let Some(matches) = matches.subcommand_matches("check") {
+        let show_file = matches.value_of("show").unwrap_or("show.toml");
+
+        if report_problems(show_file) {
+            std::process::exit(1);
+        }
+
+        println!("{}: OK", show_file);
+        return;
+    }
+
+    let show_file = matches.value_of("show").unwrap_or("show.toml");
+
+    if report_problems(show_file) {
+        std::process::exit(1);
+    }
+
     println!("Started");
 
     let (send, recv) = mpsc::channel();
+    let (status_send, status_recv) = mpsc::channel();
 
-    let show_file = matches.value_of("show").unwrap_or("show.toml");
-    let effect_pool = EffectPool::new_from_config(show_file);
-    let cue_list = CueList::new_from_config(show_file);
-    let installation = Installation::new_from_config(&effect_pool.installation());
+    let effect_pool = load(EffectPool::new_from_config(show_file));
+    let cue_list = load(CueList::new_from_config(show_file));
+    let installation = load(Installation::new_from_config(effect_pool.installation(), effect_pool.library()));
+
+    let mut outputs = load(OutputConfig::new_from_config(effect_pool.installation()));
+
+    if let Some(names) = matches.values_of("output") {
+        let names: Vec<&str> = names.collect();
+        let unknown: Vec<&str> = names.iter().cloned()
+            .filter(|name| !outputs.iter().any(|output| output.name == *name))
+            .collect();
+
+        if !unknown.is_empty() {
+            println!("No output named {} in {}", unknown.join(", "), effect_pool.installation());
+            std::process::exit(1);
+        }
+
+        outputs.retain(|output| names.contains(&output.name.as_str()));
+    }
+
+    let stall_cut = installation.stall_cut();
+    let dmx_thread = thread::spawn(move || { dmx_control::update(recv, status_send, outputs, stall_cut) });
+
+    if let Some(path) = matches.value_of("play") {
+        let recording = Recording::load(path).unwrap_or_else(|e| {
+            println!("Failed to load recording {}: {}", path, e);
+            std::process::exit(1);
+        });
+        println!("Playing {} frames from {}", recording.frames().len(), path);
+
+        recording.play(&send, matches.is_present("loop"));
+        drop(send);
+        let _ = dmx_thread.join();
+        return;
+    }
+
+    let inputs = load(InputConfig::new_from_config(effect_pool.installation()));
+    let merge_rules = load(MergeRules::new_from_config(effect_pool.installation()));
+    let mut engine = Engine::new(installation, effect_pool, cue_list, send, status_recv);
+
+    if !inputs.is_empty() {
+        let names = inputs.iter().map(|input| input.name.clone()).collect();
+        let (input_send, input_recv) = mpsc::channel();
+        dmx_input::listen(inputs, input_send);
+        engine.merge_inputs(Merger::new(merge_rules, names), input_recv);
+    }
+
+    if matches.is_present("headless") {
+        // Both were checked by their validators
+        let command_addr = matches.value_of("command-port").map(|port| {
+            let ip: IpAddr = matches.value_of("command-bind").unwrap_or("127.0.0.1").parse().unwrap();
+            SocketAddr::new(ip, port.parse().unwrap())
+        });
+        headless::run_headless(engine, command_addr);
+    } else {
+        gui::run_gui(engine);
+    }
+
+    // The engine has gone, so wait for the outputs to send their last frame
+    let _ = dmx_thread.join();
+}
+
+
+/// Whatever was loaded, or exits after printing why it couldn't be.
+fn load<T>(result: Result<T, check::Diagnostic>) -> T {
+    result.unwrap_or_else(|diagnostic| {
+        println!("{}", diagnostic);
+        std::process::exit(1);
+    })
+}
+
+/// Prints any problems with the show, returning whether there were some.
+fn report_problems(show_file: &str) -> bool {
+    let diagnostics = check::check(show_file);
+
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
 
-    thread::spawn(move || { dmx_control::update(recv) });
+    if !diagnostics.is_empty() {
+        println!("{} problem(s) found", diagnostics.len());
+    }
 
-    gui::run_gui(installation, effect_pool, cue_list, send);
+    !diagnostics.is_empty()
 }
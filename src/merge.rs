@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+use crate::dmx_input::InputFrame;
+use crate::installation_loader;
+use crate::check::Diagnostic;
+use crate::patch::{AddressRange, Universes, UNIVERSE_SIZE};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MergeMode {
+    /// Highest takes precedence: the largest value from any source
+    Htp,
+    /// Latest takes precedence: whichever source changed the channel last
+    Ltp,
+}
+
+/// Where the merged value of a channel came from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Lightboard,
+    /// Index of the input, in the order the merger was given them
+    Input(usize),
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeRules {
+    /// Mode for channels not covered by an override
+    pub mode: MergeMode,
+    /// Later overrides take precedence over earlier ones
+    pub overrides: Vec<(AddressRange, MergeMode)>,
+    /// Drop an input universe that hasn't been received for this long
+    pub timeout: Duration,
+}
+
+impl Default for MergeRules {
+    fn default() -> Self {
+        Self {
+            mode: MergeMode::Htp,
+            overrides: vec![],
+            // The sACN source loss timeout
+            timeout: Duration::from_millis(2500),
+        }
+    }
+}
+
+impl MergeRules {
+    pub fn new_from_config(config_file: &str) -> Result<Self, Diagnostic> {
+        installation_loader::build_merge_rules_from_config(config_file)
+    }
+
+    pub fn mode(&self, universe: u16, index: usize) -> MergeMode {
+        self.overrides.iter().rev()
+            .find(|(range, _)| range.contains(universe, index))
+            .map_or(self.mode, |(_, mode)| *mode)
+    }
+}
+
+struct InputSource {
+    name: String,
+    universes: Universes,
+    received: BTreeMap<u16, Instant>,
+}
+
+/// Merges lightboard's own frames with DMX received from inputs, channel by
+/// channel, and remembers which source each channel came from.
+pub struct Merger {
+    rules: MergeRules,
+    inputs: Vec<InputSource>,
+    previous: Universes,
+    ltp_owners: BTreeMap<u16, Vec<Source>>,
+    owners: BTreeMap<u16, Vec<Source>>,
+}
+
+fn changed(previous: Option<&Vec<u8>>, data: &[u8], i: usize) -> bool {
+    previous.and_then(|previous| previous.get(i)).cloned().unwrap_or(0) != data[i]
+}
+
+impl Merger {
+    pub fn new(rules: MergeRules, input_names: Vec<String>) -> Self {
+        let inputs = input_names.into_iter().map(|name| InputSource {
+            name,
+            universes: Universes::new(),
+            received: BTreeMap::new(),
+        }).collect();
+
+        Self {
+            rules,
+            inputs,
+            previous: Universes::new(),
+            ltp_owners: BTreeMap::new(),
+            owners: BTreeMap::new(),
+        }
+    }
+
+    pub fn receive(&mut self, frame: InputFrame, now: Instant) {
+        let input = match self.inputs.get_mut(frame.input) {
+            Some(input) => input,
+            None => return,
+        };
+
+        let mut data = frame.data;
+        data.resize(UNIVERSE_SIZE, 0);
+
+        let previous = input.universes.get(&frame.universe);
+        let owners = self.ltp_owners.entry(frame.universe)
+                        .or_insert_with(|| vec![Source::Lightboard; UNIVERSE_SIZE]);
+
+        for (i, owner) in owners.iter_mut().enumerate() {
+            if changed(previous, &data, i) {
+                *owner = Source::Input(frame.input);
+            }
+        }
+
+        input.universes.insert(frame.universe, data);
+        input.received.insert(frame.universe, now);
+    }
+
+    /// Merge lightboard's frame with the latest data from each input.
+    pub fn merge(&mut self, local: &Universes, now: Instant) -> Universes {
+        self.drop_lost_inputs(now);
+
+        for (universe, data) in local.iter() {
+            let previous = self.previous.get(universe);
+            let owners = self.ltp_owners.entry(*universe)
+                            .or_insert_with(|| vec![Source::Lightboard; UNIVERSE_SIZE]);
+
+            for (i, owner) in owners.iter_mut().enumerate().take(data.len()) {
+                if changed(previous, data, i) {
+                    *owner = Source::Lightboard;
+                }
+            }
+        }
+
+        self.previous = local.clone();
+
+        let mut universes: BTreeSet<u16> = local.keys().cloned().collect();
+
+        for input in self.inputs.iter() {
+            universes.extend(input.universes.keys());
+        }
+
+        let mut merged = Universes::new();
+        self.owners.clear();
+
+        for universe in universes {
+            let mut data = local.get(&universe).cloned().unwrap_or_default();
+            data.resize(UNIVERSE_SIZE, 0);
+            let mut owners = vec![Source::Lightboard; UNIVERSE_SIZE];
+
+            for (i, (value, owner)) in data.iter_mut().zip(owners.iter_mut()).enumerate() {
+                match self.rules.mode(universe, i) {
+                    MergeMode::Htp => {
+                        for (n, input) in self.inputs.iter().enumerate() {
+                            if let Some(input_data) = input.universes.get(&universe) {
+                                if input_data[i] > *value {
+                                    *value = input_data[i];
+                                    *owner = Source::Input(n);
+                                }
+                            }
+                        }
+                    },
+                    MergeMode::Ltp => {
+                        let ltp_owner = self.ltp_owners.get(&universe).map(|owners| owners[i]);
+
+                        if let Some(Source::Input(n)) = ltp_owner {
+                            if let Some(input_data) = self.inputs[n].universes.get(&universe) {
+                                *value = input_data[i];
+                                *owner = Source::Input(n);
+                            }
+                        }
+                    },
+                }
+            }
+
+            merged.insert(universe, data);
+            self.owners.insert(universe, owners);
+        }
+
+        merged
+    }
+
+    // Forget inputs that have stopped sending, handing their LTP channels
+    // back to lightboard
+    fn drop_lost_inputs(&mut self, now: Instant) {
+        let timeout = self.rules.timeout;
+
+        for (n, input) in self.inputs.iter_mut().enumerate() {
+            let lost: Vec<u16> = input.received.iter()
+                .filter(|(_, received)| now.duration_since(**received) > timeout)
+                .map(|(universe, _)| *universe)
+                .collect();
+
+            for universe in lost {
+                input.universes.remove(&universe);
+                input.received.remove(&universe);
+
+                if let Some(owners) = self.ltp_owners.get_mut(&universe) {
+                    for owner in owners.iter_mut().filter(|owner| **owner == Source::Input(n)) {
+                        *owner = Source::Lightboard;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The source of each merged channel, by universe.
+    pub fn owners(&self) -> &BTreeMap<u16, Vec<Source>> {
+        &self.owners
+    }
+
+    pub fn input_name(&self, input: usize) -> &str {
+        &self.inputs[input].name
+    }
+
+    /// Each input's name and the universes currently being received from it.
+    pub fn inputs(&self) -> Vec<(&str, Vec<u16>)> {
+        self.inputs.iter()
+            .map(|input| (input.name.as_str(), input.universes.keys().cloned().collect()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universe(values: &[(usize, u8)]) -> Vec<u8> {
+        let mut data = vec![0; UNIVERSE_SIZE];
+
+        for (i, value) in values {
+            data[*i] = *value;
+        }
+
+        data
+    }
+
+    fn local(values: &[(usize, u8)]) -> Universes {
+        let mut universes = Universes::new();
+        universes.insert(1, universe(values));
+        universes
+    }
+
+    fn frame(values: &[(usize, u8)]) -> InputFrame {
+        InputFrame { input: 0, universe: 1, data: universe(values) }
+    }
+
+    fn merger(rules: MergeRules) -> Merger {
+        Merger::new(rules, vec!["console".to_owned()])
+    }
+
+    #[test]
+    fn test_htp_takes_highest() {
+        let mut merger = merger(MergeRules::default());
+        let now = Instant::now();
+
+        merger.receive(frame(&[(0, 100), (1, 50)]), now);
+        let merged = merger.merge(&local(&[(0, 50), (1, 200)]), now);
+
+        assert_eq!([100, 200], merged[&1][..2]);
+        assert_eq!([Source::Input(0), Source::Lightboard], merger.owners()[&1][..2]);
+    }
+
+    #[test]
+    fn test_ltp_follows_latest_change() {
+        let mut merger = merger(MergeRules {
+            mode: MergeMode::Ltp,
+            overrides: vec![("1.2".parse().unwrap(), MergeMode::Htp)],
+            ..MergeRules::default()
+        });
+        let now = Instant::now();
+
+        merger.merge(&local(&[(0, 200), (1, 200)]), now);
+        merger.receive(frame(&[(0, 10), (1, 10)]), now);
+        let merged = merger.merge(&local(&[(0, 200), (1, 200)]), now);
+        assert_eq!([10, 200], merged[&1][..2]);
+        assert_eq!(Source::Input(0), merger.owners()[&1][0]);
+
+        let merged = merger.merge(&local(&[(0, 150), (1, 200)]), now);
+        assert_eq!(150, merged[&1][0]);
+        assert_eq!(Source::Lightboard, merger.owners()[&1][0]);
+    }
+
+    #[test]
+    fn test_lost_input_is_dropped() {
+        let mut merger = merger(MergeRules { mode: MergeMode::Ltp, ..MergeRules::default() });
+        let start = Instant::now();
+
+        merger.receive(frame(&[(0, 10)]), start);
+        assert_eq!(10, merger.merge(&local(&[]), start)[&1][0]);
+
+        let later = start + Duration::from_secs(3);
+        assert_eq!(0, merger.merge(&local(&[]), later)[&1][0]);
+        assert_eq!(Source::Lightboard, merger.owners()[&1][0]);
+        assert_eq!(vec![("console", vec![])], merger.inputs());
+    }
+}
@@ -0,0 +1,659 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use serde_json::Value;
+
+// Converts Open Fixture Library (https://open-fixture-library.org) fixture
+// JSON into lightboard fixture definitions. Only the parts of the format
+// that map onto element kinds are read; anything else is reported.
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OflFixture {
+    name: String,
+    #[serde(default)]
+    available_channels: HashMap<String, OflChannel>,
+    #[serde(default)]
+    wheels: HashMap<String, OflWheel>,
+    modes: Vec<OflMode>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OflChannel {
+    #[serde(default)]
+    fine_channel_aliases: Vec<String>,
+    default_value: Option<Value>,
+    #[serde(default)]
+    constant: bool,
+    capability: Option<OflCapability>,
+    capabilities: Option<Vec<OflCapability>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct OflCapability {
+    #[serde(rename = "type")]
+    kind: String,
+    dmx_range: Option<(u32, u32)>,
+    color: Option<String>,
+    angle_start: Option<String>,
+    angle_end: Option<String>,
+    slot_number: Option<f32>,
+    wheel: Option<String>,
+    speed_start: Option<String>,
+    speed_end: Option<String>,
+    shutter_effect: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OflWheel {
+    slots: Vec<OflSlot>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OflSlot {
+    #[serde(rename = "type")]
+    kind: String,
+    name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct OflMode {
+    name: String,
+    short_name: Option<String>,
+    channels: Vec<Value>,
+}
+
+/// A fixture definition converted from the Open Fixture Library, and what
+/// couldn't be converted.
+#[derive(Debug)]
+pub struct Conversion {
+    pub name: String,
+    pub toml: String,
+    pub problems: Vec<String>,
+}
+
+/// Convert an OFL fixture file and write it to `output`, or to a file in
+/// fixtures/ named after the fixture. Existing files aren't overwritten.
+pub fn import(path: &str, output: Option<&str>) -> Result<Conversion, String> {
+    let json = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let conversion = convert(&json)?;
+
+    let output = match output {
+        Some(output) => output.to_owned(),
+        None => format!("fixtures/{}.toml", slug(&conversion.name)),
+    };
+
+    if Path::new(&output).exists() {
+        return Err(format!("{} already exists", output));
+    }
+
+    fs::write(&output, &conversion.toml).map_err(|e| e.to_string())?;
+    println!("Wrote {}", output);
+    Ok(conversion)
+}
+
+pub fn convert(json: &str) -> Result<Conversion, String> {
+    let fixture: OflFixture = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut problems = vec![];
+    let mut toml = format!("# Converted from the Open Fixture Library definition of {}\n", fixture.name);
+
+    for mode in fixture.modes.iter() {
+        let mode_name = mode.short_name.as_ref().unwrap_or(&mode.name);
+        let mut converter = ModeConverter::new(&fixture, mode);
+        converter.convert();
+
+        toml.push_str(&format!("[[modes]]\nname = {}\nnum_channels = {}\n",
+                               toml_string(mode_name), mode.channels.len()));
+
+        for (name, fields) in converter.elements.iter() {
+            toml.push_str(&format!("elements.{} = {{{}}}\n", name, fields.join(", ")));
+        }
+
+        if !converter.fixed.is_empty() {
+            let fixed: Vec<String> = converter.fixed.iter()
+                .map(|(channel, value)| format!("{} = {}", channel, value))
+                .collect();
+            toml.push_str(&format!("fixed = {{{}}}\n", fixed.join(", ")));
+        }
+
+        toml.push('\n');
+
+        problems.extend(converter.problems.into_iter()
+                            .map(|problem| format!("Mode {}: {}", mode_name, problem)));
+    }
+
+    Ok(Conversion { name: fixture.name, toml: toml.trim_end().to_owned() + "\n", problems })
+}
+
+// Each colour emitter's channel name in lightboard fixture definitions
+fn emitter(color: &str) -> Option<&'static str> {
+    match color {
+        "Red" => Some("r"),
+        "Green" => Some("g"),
+        "Blue" => Some("b"),
+        "White" => Some("w"),
+        "Amber" => Some("a"),
+        "UV" => Some("uv"),
+        "Cyan" => Some("c"),
+        "Magenta" => Some("m"),
+        "Yellow" => Some("y"),
+        _ => None,
+    }
+}
+
+/// Lowercase with anything but letters and digits replaced by underscores,
+/// for element and file names.
+fn slug(name: &str) -> String {
+    let slug: String = name.to_lowercase().chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    slug.split('_').filter(|part| !part.is_empty()).collect::<Vec<_>>().join("_")
+}
+
+fn toml_string(s: &str) -> String {
+    toml::Value::String(s.to_owned()).to_string()
+}
+
+fn parse_angle(angle: &Option<String>) -> Option<f32> {
+    angle.as_ref()?.trim_end_matches("deg").parse().ok()
+}
+
+// How fast a speed such as "slow CW" or "10rpm" is, to order speed ranges
+fn speed_rank(speed: &str) -> Option<f32> {
+    let speed = speed.to_lowercase();
+
+    if speed.starts_with("stop") {
+        Some(0.0)
+    } else if speed.starts_with("slow") {
+        Some(1.0)
+    } else if speed.starts_with("fast") {
+        Some(f32::MAX)
+    } else {
+        let number: String = speed.chars().take_while(|c| c.is_ascii_digit() || *c == '.' || *c == '-').collect();
+        number.parse::<f32>().ok().map(f32::abs)
+    }
+}
+
+// The DMX value for a channel's default, which OFL gives as a number or
+// a percentage
+fn dmx_value(value: &Value) -> Option<u8> {
+    match value {
+        Value::Number(n) => n.as_u64().map(|n| n.min(255) as u8),
+        Value::String(s) => {
+            let percent: f32 = s.trim_end_matches('%').parse().ok()?;
+            Some((percent / 100.0 * 255.0).round().clamp(0.0, 255.0) as u8)
+        },
+        _ => None,
+    }
+}
+
+fn dmx_range(capability: &OflCapability) -> (u8, u8) {
+    let (start, end) = capability.dmx_range.unwrap_or((0, 255));
+    (start.min(255) as u8, end.min(255) as u8)
+}
+
+struct ModeConverter<'a> {
+    fixture: &'a OflFixture,
+    mode: &'a OflMode,
+    elements: Vec<(String, Vec<String>)>,
+    fixed: Vec<(usize, u8)>,
+    problems: Vec<String>,
+    // The index of the wheel element for each wheel name
+    wheel_elements: HashMap<String, usize>,
+    params: Vec<String>,
+}
+
+impl<'a> ModeConverter<'a> {
+    fn new(fixture: &'a OflFixture, mode: &'a OflMode) -> Self {
+        Self {
+            fixture,
+            mode,
+            elements: vec![],
+            fixed: vec![],
+            problems: vec![],
+            wheel_elements: HashMap::new(),
+            params: vec![],
+        }
+    }
+
+    fn channel_names(&self) -> Vec<(usize, Option<&'a str>)> {
+        self.mode.channels.iter().enumerate().map(|(i, channel)| (i + 1, channel.as_str())).collect()
+    }
+
+    // The channel a parameter is on, as it appears in a fixture definition:
+    // the coarse channel, with its fine channel if the mode has one
+    fn channel_config(&mut self, name: &str, index: usize) -> Option<String> {
+        let fine = self.fixture.available_channels.get(name)
+            .and_then(|channel| channel.fine_channel_aliases.first())
+            .and_then(|alias| self.mode.channels.iter().position(|c| c.as_str() == Some(alias)))
+            .map(|i| i + 1);
+
+        if index > 255 || matches!(fine, Some(fine) if fine > 255) {
+            self.problems.push(format!("channel {} ({}) is beyond channel 255", index, name));
+            return None;
+        }
+
+        Some(match fine {
+            Some(fine) => format!("[{}, {}]", index, fine),
+            None => index.to_string(),
+        })
+    }
+
+    fn add_element(&mut self, name: &str, fields: Vec<String>) -> usize {
+        let mut unique = name.to_owned();
+        let mut n = 2;
+
+        while self.elements.iter().any(|(existing, _)| *existing == unique) {
+            unique = format!("{}_{}", name, n);
+            n += 1;
+        }
+
+        self.elements.push((unique, fields));
+        self.elements.len() - 1
+    }
+
+    fn convert(&mut self) {
+        let fixture = self.fixture;
+        let fine_aliases: Vec<&str> = fixture.available_channels.values()
+            .flat_map(|channel| channel.fine_channel_aliases.iter().map(|alias| alias.as_str()))
+            .collect();
+
+        let mut intensities = vec![];
+        let mut colors: Vec<Vec<(&'static str, String)>> = vec![];
+        let mut position = vec![];
+
+        for (index, name) in self.channel_names() {
+            let name = match name {
+                Some(name) => name,
+                None if self.mode.channels[index - 1].is_null() => continue,
+                None => {
+                    self.problems.push(format!("channel {} is a matrix channel, which isn't supported", index));
+                    continue;
+                },
+            };
+
+            if fine_aliases.contains(&name) {
+                continue;
+            }
+
+            let channel = match fixture.available_channels.get(name) {
+                Some(channel) => channel,
+                None => {
+                    self.problems.push(format!("channel {} ({}) isn't defined", index, name));
+                    continue;
+                },
+            };
+
+            // Constant channels are held at their default for every mode
+            if channel.constant {
+                if let Some(value) = channel.default_value.as_ref().and_then(dmx_value) {
+                    self.fixed.push((index, value));
+                    continue;
+                }
+            }
+
+            let channel_config = match self.channel_config(name, index) {
+                Some(config) => config,
+                None => continue,
+            };
+
+            let capabilities = match (&channel.capability, &channel.capabilities) {
+                (Some(capability), _) => vec![capability.clone()],
+                (None, Some(capabilities)) => capabilities.clone(),
+                (None, None) => vec![],
+            };
+
+            if capabilities.len() == 1 {
+                let capability = &capabilities[0];
+
+                match capability.kind.as_str() {
+                    "Intensity" => {
+                        intensities.push((name, channel_config));
+                        continue;
+                    },
+                    "ColorIntensity" => {
+                        if let Some(emitter) = capability.color.as_deref().and_then(emitter) {
+                            // A repeated emitter starts another colour element
+                            let repeated = match colors.last() {
+                                Some(color) => color.iter().any(|(e, _)| *e == emitter),
+                                None => true,
+                            };
+
+                            if repeated {
+                                colors.push(vec![]);
+                            }
+
+                            colors.last_mut().unwrap().push((emitter, channel_config));
+                            continue;
+                        }
+
+                        self.problems.push(format!("channel {} ({}): {} emitters aren't supported, kept as a raw parameter",
+                                                   index, name, capability.color.as_deref().unwrap_or("unknown")));
+                    },
+                    "Pan" | "Tilt" => {
+                        let axis = capability.kind.to_lowercase();
+                        position.push(format!("{}={}", axis, channel_config));
+
+                        if let (Some(start), Some(end)) = (parse_angle(&capability.angle_start),
+                                                           parse_angle(&capability.angle_end)) {
+                            position.push(format!("{}_range={}", axis, (end - start).abs()));
+
+                            if end < start {
+                                position.push(format!("invert_{}=true", axis));
+                            }
+                        }
+                        continue;
+                    },
+                    _ => {},
+                }
+            }
+
+            if capabilities.iter().any(|capability| capability.kind == "WheelSlot") {
+                self.add_wheel(index, name, &channel_config, &capabilities);
+                continue;
+            }
+
+            if capabilities.iter().any(|capability| capability.kind == "WheelSlotRotation")
+                && self.add_rotation(index, name, &channel_config, &capabilities)
+            {
+                continue;
+            }
+
+            self.add_param(index, name, &channel_config, channel, &capabilities);
+        }
+
+        // A single dimmer with a single set of colour emitters is the colour
+        // element's dimmer
+        if intensities.len() == 1 && colors.len() == 1 {
+            let (_, channel_config) = intensities.remove(0);
+            colors[0].push(("i", channel_config));
+        }
+
+        for (name, channel_config) in intensities {
+            self.add_element(&slug(name), vec!["kind=\"intensity\"".to_owned(), format!("i={}", channel_config)]);
+        }
+
+        for color in colors {
+            let has = |emitter: &str| color.iter().any(|(e, _)| *e == emitter);
+
+            let kind = if has("c") || has("m") || has("y") {
+                "cmy"
+            } else {
+                match (has("a"), has("w"), has("uv")) {
+                    (false, false, false) => "rgbi",
+                    (false, false, true) => "rgbiu",
+                    (false, true, false) => "rgbw",
+                    (false, true, true) => "rgbwu",
+                    (true, false, false) => "rgba",
+                    (true, true, false) => "rgbaw",
+                    (true, _, true) => "rgbawu",
+                }
+            };
+
+            let mut fields = vec![format!("kind=\"{}\"", kind)];
+            fields.extend(color.iter().map(|(emitter, channel_config)| format!("{}={}", emitter, channel_config)));
+            self.add_element("color", fields);
+        }
+
+        if !position.is_empty() {
+            position.insert(0, "kind=\"position\"".to_owned());
+            self.add_element("position", position);
+        }
+
+        if !self.params.is_empty() {
+            let params = format!("params=[\n    {},\n]", self.params.join(",\n    "));
+            self.add_element("params", vec!["kind=\"params\"".to_owned(), params]);
+        }
+    }
+
+    fn add_wheel(&mut self, index: usize, name: &str, channel_config: &str, capabilities: &[OflCapability]) {
+        let mut slots = vec![];
+        let mut scroll = vec![];
+        let mut is_color = false;
+
+        for capability in capabilities {
+            let (start, end) = dmx_range(capability);
+
+            match capability.kind.as_str() {
+                "WheelSlot" => {
+                    let wheel_name = capability.wheel.as_deref().unwrap_or(name);
+                    let slot_number = match capability.slot_number {
+                        // Split slots show two colours or gobos at once
+                        Some(slot_number) if slot_number.fract() == 0.0 => slot_number as usize,
+                        _ => continue,
+                    };
+
+                    let slot = self.fixture.wheels.get(wheel_name)
+                        .and_then(|wheel| wheel.slots.get(slot_number.wrapping_sub(1)));
+
+                    let slot_name = match slot {
+                        Some(slot) => {
+                            is_color |= slot.kind == "Color";
+
+                            match &slot.name {
+                                Some(name) => name.to_lowercase(),
+                                None if slot.kind == "Gobo" => format!("gobo {}", slot_number),
+                                None => slot.kind.to_lowercase(),
+                            }
+                        },
+                        None => format!("slot {}", slot_number),
+                    };
+
+                    slots.push(format!("{{name={}, dmx=[{}, {}]}}", toml_string(&slot_name), start, end));
+                },
+                "WheelRotation" => {
+                    match self.speed_range(capability) {
+                        Some((direction, range)) => scroll.push(format!("scroll_{}={}", direction, range)),
+                        None => self.problems.push(format!("channel {} ({}): couldn't tell the scroll direction of DMX {}-{}",
+                                                           index, name, start, end)),
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let kind = if is_color { "color_wheel" } else { "gobo" };
+        let mut fields = vec![format!("kind=\"{}\"", kind), format!("wheel={}", channel_config)];
+        fields.extend(scroll);
+        fields.push(format!("slots=[\n    {},\n]", slots.join(",\n    ")));
+
+        let element = self.add_element(&slug(name), fields);
+        let wheel_name = capabilities.iter().find_map(|capability| capability.wheel.clone())
+                            .unwrap_or_else(|| name.to_owned());
+        self.wheel_elements.insert(wheel_name, element);
+    }
+
+    // Gobo rotation goes on the element of the gobo wheel it turns, which
+    // needs to have come first in the mode
+    fn add_rotation(&mut self, index: usize, name: &str, channel_config: &str,
+                    capabilities: &[OflCapability]) -> bool
+    {
+        // Without a wheel named, only a fixture's one wheel can be meant
+        let wheel = capabilities.iter().find_map(|capability| capability.wheel.clone())
+            .or_else(|| match self.wheel_elements.len() {
+                1 => self.wheel_elements.keys().next().cloned(),
+                _ => None,
+            });
+
+        let element = match wheel.and_then(|wheel| self.wheel_elements.get(&wheel)) {
+            Some(element) => *element,
+            None => return false,
+        };
+
+        let mut fields = vec![format!("rotation={}", channel_config)];
+
+        for capability in capabilities.iter().filter(|capability| capability.kind == "WheelSlotRotation") {
+            match self.speed_range(capability) {
+                Some((direction, range)) => fields.push(format!("rotation_{}={}", direction, range)),
+                None => {
+                    let (start, end) = dmx_range(capability);
+
+                    if matches!(capability.speed_start.as_deref(), Some(speed) if speed.starts_with("stop")) {
+                        fields.push(format!("rotation_stop={}", start));
+                    } else {
+                        self.problems.push(format!("channel {} ({}): couldn't tell the rotation direction of DMX {}-{}",
+                                                   index, name, start, end));
+                    }
+                },
+            }
+        }
+
+        // Keep the slots last, since they span several lines
+        let element_fields = &mut self.elements[element].1;
+        let slots = element_fields.pop();
+        element_fields.extend(fields);
+        element_fields.extend(slots);
+        true
+    }
+
+    // The direction of a rotation capability and its DMX range from slowest
+    // to fastest
+    fn speed_range(&self, capability: &OflCapability) -> Option<(&'static str, String)> {
+        let start = capability.speed_start.as_deref()?;
+        let end = capability.speed_end.as_deref().unwrap_or(start);
+        let speeds = format!("{} {}", start, end).to_uppercase();
+
+        let direction = if speeds.contains("CCW") {
+            "ccw"
+        } else if speeds.contains("CW") {
+            "cw"
+        } else {
+            return None;
+        };
+
+        let (low, high) = dmx_range(capability);
+        let range = if speed_rank(start)? <= speed_rank(end)? { (low, high) } else { (high, low) };
+        Some((direction, format!("[{}, {}]", range.0, range.1)))
+    }
+
+    fn add_param(&mut self, index: usize, name: &str, channel_config: &str, channel: &OflChannel,
+                 capabilities: &[OflCapability])
+    {
+        let mut fields = vec![format!("name={}", toml_string(&slug(name))), format!("channel={}", channel_config)];
+        let mut range = (0.0, 255.0);
+        let mut dmx = (0, 255);
+
+        match capabilities {
+            [capability] => {
+                if let (Some(start), Some(end)) = (parse_angle(&capability.angle_start),
+                                                   parse_angle(&capability.angle_end)) {
+                    range = (start, end);
+                    fields.push(format!("range=[{}, {}]", start, end));
+                }
+
+                dmx = dmx_range(capability);
+
+                if dmx != (0, 255) {
+                    fields.push(format!("dmx=[{}, {}]", dmx.0, dmx.1));
+                }
+            },
+            _ => {
+                self.problems.push(format!("channel {} ({}) has several functions, kept as a raw 0-255 parameter",
+                                           index, name));
+            },
+        }
+
+        // Without a default, leave shutters open so the fixture gives light
+        let open = capabilities.iter()
+            .find(|capability| capability.shutter_effect.as_deref() == Some("Open"))
+            .map(|capability| dmx_range(capability).0);
+
+        if let Some(default) = channel.default_value.as_ref().and_then(dmx_value).or(open) {
+            // Defaults are DMX values, but parameters rest at a value in their range
+            let along = if dmx.1 == dmx.0 {
+                0.0
+            } else {
+                ((default as f32 - dmx.0 as f32) / (dmx.1 as f32 - dmx.0 as f32)).clamp(0.0, 1.0)
+            };
+            let default = range.0 + (range.1 - range.0) * along;
+
+            if default != range.0 {
+                fields.push(format!("default={}", default));
+            }
+        }
+
+        self.params.push(format!("{{{}}}", fields.join(", ")));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installation_loader;
+    use crate::fixture::ElementKind;
+
+    const SPOT: &str = r##"{
+        "name": "Test Spot 250",
+        "availableChannels": {
+            "Pan": {"fineChannelAliases": ["Pan fine"], "capability": {"type": "Pan", "angleStart": "0deg", "angleEnd": "540deg"}},
+            "Tilt": {"capability": {"type": "Tilt", "angleStart": "270deg", "angleEnd": "0deg"}},
+            "Dimmer": {"capability": {"type": "Intensity"}},
+            "Gobo Wheel": {"capabilities": [
+                {"dmxRange": [0, 9], "type": "WheelSlot", "slotNumber": 1},
+                {"dmxRange": [10, 19], "type": "WheelSlot", "slotNumber": 2},
+                {"dmxRange": [20, 127], "type": "WheelRotation", "speedStart": "fast CW", "speedEnd": "slow CW"},
+                {"dmxRange": [128, 255], "type": "WheelRotation", "speedStart": "slow CCW", "speedEnd": "fast CCW"}
+            ]},
+            "Shutter": {"capabilities": [
+                {"dmxRange": [0, 31], "type": "ShutterStrobe", "shutterEffect": "Closed"},
+                {"dmxRange": [32, 63], "type": "ShutterStrobe", "shutterEffect": "Open"},
+                {"dmxRange": [64, 255], "type": "ShutterStrobe", "shutterEffect": "Strobe"}
+            ]},
+            "Zoom": {"capability": {"type": "Zoom", "angleStart": "10deg", "angleEnd": "40deg"}},
+            "Red": {"capability": {"type": "ColorIntensity", "color": "Red"}},
+            "Green": {"capability": {"type": "ColorIntensity", "color": "Green"}},
+            "Blue": {"capability": {"type": "ColorIntensity", "color": "Blue"}},
+            "Lime": {"capability": {"type": "ColorIntensity", "color": "Lime"}},
+            "Mode": {"defaultValue": 200, "constant": true, "capability": {"type": "Maintenance"}}
+        },
+        "wheels": {
+            "Gobo Wheel": {"slots": [{"type": "Open"}, {"type": "Gobo", "name": "Star"}]}
+        },
+        "modes": [
+            {"name": "Basic", "shortName": "basic", "channels": [
+                "Pan", "Pan fine", "Tilt", "Dimmer", "Gobo Wheel", "Shutter", "Zoom", null, "Mode"
+            ]},
+            {"name": "Colour", "channels": ["Dimmer", "Red", "Green", "Blue", "Lime", "Missing"]}
+        ]
+    }"##;
+
+    #[test]
+    fn test_convert_modes() {
+        let conversion = convert(SPOT).unwrap();
+        let toml = conversion.toml;
+        assert_eq!("Test Spot 250", conversion.name);
+        assert_eq!("test_spot_250", slug(&conversion.name));
+
+        assert!(toml.contains("[[modes]]\nname = \"basic\"\nnum_channels = 9\n"));
+        assert!(toml.contains("elements.dimmer = {kind=\"intensity\", i=4}\n"));
+        assert!(toml.contains("elements.position = {kind=\"position\", pan=[1, 2], pan_range=540, \
+                               tilt=3, tilt_range=270, invert_tilt=true}\n"));
+        assert!(toml.contains("elements.gobo_wheel = {kind=\"gobo\", wheel=5, scroll_cw=[127, 20], \
+                               scroll_ccw=[128, 255], slots=[\n    {name=\"open\", dmx=[0, 9]},\n    \
+                               {name=\"star\", dmx=[10, 19]},\n]}\n"));
+        assert!(toml.contains("{name=\"shutter\", channel=6, default=32}"));
+        assert!(toml.contains("{name=\"zoom\", channel=7, range=[10, 40]}"));
+        assert!(toml.contains("fixed = {9 = 200}\n"));
+
+        assert!(toml.contains("name = \"Colour\"\nnum_channels = 6\n"));
+        assert!(toml.contains("elements.color = {kind=\"rgbi\", r=2, g=3, b=4, i=1}\n"));
+
+        assert_eq!(vec![
+            "Mode basic: channel 6 (Shutter) has several functions, kept as a raw 0-255 parameter",
+            "Mode Colour: channel 5 (Lime): Lime emitters aren't supported, kept as a raw parameter",
+            "Mode Colour: channel 6 (Missing) isn't defined",
+        ], conversion.problems);
+
+        // The result is a valid fixture definition
+        let modes = installation_loader::load_fixture_def("fixtures/test_spot_250.toml", &toml).unwrap();
+        let basic = &modes["basic"];
+        assert!(matches!(basic["position"].kind(), ElementKind::Position{..}));
+        assert!(matches!(basic["gobo_wheel"].kind(), ElementKind::Wheel{..}));
+        assert_eq!(Some(1), basic["gobo_wheel"].wheel().slot_index("star"));
+        assert!(matches!(modes["Colour"]["color"].kind(), ElementKind::Rgbi{..}));
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+use std::thread;
+use std::time::Duration;
+use dmx::{self, DmxTransmitter};
+use crate::artnet::ArtNetNode;
+use crate::sacn::{SacnSource, SacnConfig};
+use crate::patch::{Universes, UNIVERSE_SIZE};
+use crate::installation_loader;
+use crate::check::Diagnostic;
+use crate::recording::Recorder;
+
+/// The fastest an output can be asked to send, in frames per second
+pub const MAX_RATE: f32 = 1000.0;
+
+/// Something the DMX thread can send frames to.
+pub trait DmxOutput {
+    fn send(&mut self, universes: &Universes) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone)]
+pub enum OutputKind {
+    /// Enttec-style USB dongle, which carries a single patch universe
+    Serial {
+        device: String,
+        universe: u16,
+        /// Time from starting the break to sending data, which covers the
+        /// break and mark-after-break
+        break_us: u64,
+    },
+    /// `universe` is the port-address for patch universe 1
+    ArtNet {
+        bind: String,
+        target: String,
+        universe: u16,
+    },
+    Sacn(SacnConfig),
+    /// Record every frame to a file that can be played back later
+    File(String),
+    Null,
+}
+
+impl OutputKind {
+    /// Whether the output can be reopened after sending fails. Reopening a
+    /// recording would start it over and lose everything recorded so far.
+    pub fn reopens(&self) -> bool {
+        !matches!(self, OutputKind::File(_))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputTiming {
+    /// Frames per second
+    pub rate: f32,
+    /// Only send frames that differ from the last one sent
+    pub send_on_change: bool,
+    /// With `send_on_change`, resend an unchanged frame after this long
+    pub keep_alive: Duration,
+}
+
+impl Default for OutputTiming {
+    fn default() -> Self {
+        Self {
+            rate: 40.0,
+            send_on_change: false,
+            keep_alive: Duration::from_secs(1),
+        }
+    }
+}
+
+impl OutputTiming {
+    pub fn period(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.rate.max(0.1))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputConfig {
+    pub name: String,
+    pub kind: OutputKind,
+    pub timing: OutputTiming,
+}
+
+impl OutputConfig {
+    pub fn new(name: &str, kind: OutputKind) -> Self {
+        Self { name: name.to_owned(), kind, timing: OutputTiming::default() }
+    }
+
+    pub fn new_from_config(config_file: &str) -> Result<Vec<OutputConfig>, Diagnostic> {
+        installation_loader::build_outputs_from_config(config_file)
+    }
+
+    pub fn open(&self) -> io::Result<Box<dyn DmxOutput>> {
+        let output: Box<dyn DmxOutput> = match &self.kind {
+            OutputKind::Serial{device, universe, break_us} => Box::new(SerialOutput {
+                port: dmx::open_serial(device)?,
+                universe: *universe,
+                break_time: Duration::from_micros(*break_us),
+            }),
+            OutputKind::ArtNet{bind, target, universe} => {
+                Box::new(ArtNetNode::new(bind, target, *universe)?)
+            },
+            OutputKind::Sacn(config) => Box::new(SacnSource::new(config.clone())?),
+            OutputKind::File(path) => Box::new(Recorder::create(path)?),
+            OutputKind::Null => Box::new(NullOutput),
+        };
+
+        Ok(output)
+    }
+}
+
+pub struct SerialOutput<T: DmxTransmitter> {
+    port: T,
+    universe: u16,
+    break_time: Duration,
+}
+
+impl<T: DmxTransmitter> DmxOutput for SerialOutput<T> {
+    fn send(&mut self, universes: &Universes) -> io::Result<()> {
+        let data = universes.get(&self.universe).map(|d| d.as_slice()).unwrap_or(&[]);
+
+        // Always send a full universe, after a zero start code
+        let count = data.len().min(UNIVERSE_SIZE);
+        let mut packet = vec![0; UNIVERSE_SIZE + 1];
+        packet[1..=count].copy_from_slice(&data[..count]);
+
+        self.port.send_break()?;
+        thread::sleep(self.break_time);
+        self.port.send_raw_data(&packet)?;
+        Ok(())
+    }
+}
+
+pub struct NullOutput;
+
+impl DmxOutput for NullOutput {
+    fn send(&mut self, _universes: &Universes) -> io::Result<()> {
+        Ok(())
+    }
+}
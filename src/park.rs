@@ -0,0 +1,120 @@
+use std::fmt;
+use std::str::FromStr;
+use crate::fixture::ElementKind;
+use crate::patch::Address;
+
+/// What a park pins: a fixture element, written `fixture:element`, or a raw
+/// DMX address such as `2.301`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParkTarget {
+    Element {
+        fixture: String,
+        element: String,
+    },
+    Address(Address),
+}
+
+impl FromStr for ParkTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.find(':') {
+            Some(i) if i > 0 && i < s.len() - 1 => Ok(ParkTarget::Element {
+                fixture: s[..i].to_owned(),
+                element: s[i + 1..].to_owned(),
+            }),
+            Some(_) => Err(format!("Invalid park target \"{}\"", s)),
+            None => Ok(ParkTarget::Address(s.parse()?)),
+        }
+    }
+}
+
+impl fmt::Display for ParkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParkTarget::Element{fixture, element} => write!(f, "{}:{}", fixture, element),
+            ParkTarget::Address(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+/// The element kind for a parked value, which is read the same way as effect
+/// values: 0-255 for intensities, 0xRRGGBB for colours, the slot number for
+/// wheels. Parked colours are at full, whatever dimmer effects are running.
+pub fn parked_kind(kind: &ElementKind, value: i32) -> Option<ElementKind> {
+    let intensity = (value & 0xff) as f32 / 255.0;
+
+    match kind {
+        ElementKind::Intensity(_) => Some(ElementKind::Intensity(intensity)),
+        ElementKind::Smoke(_) => Some(ElementKind::Smoke(intensity)),
+        ElementKind::Rgbi{..} => Some(ElementKind::Rgbi{rgb: value.into(), i: None}),
+        ElementKind::Rgbiu{uv, ..} => Some(ElementKind::Rgbiu{rgb: value.into(), i: None, uv: *uv}),
+        ElementKind::Uv(_) => Some(ElementKind::Uv(intensity)),
+        ElementKind::Wheel{..} => {
+            Some(ElementKind::Wheel{slot: value.max(0) as usize, rotation: 0.0, scroll: 0.0})
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use crate::installation::Installation;
+    use crate::library::Library;
+    use crate::light::Color;
+    use crate::fixture::Element;
+    use crate::fixture::tests::single_element_fixture;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(Ok(ParkTarget::Element { fixture: "par".to_owned(), element: "1".to_owned() }),
+                   "par:1".parse());
+        assert_eq!(Ok(ParkTarget::Address(Address::new(2, 301))), "2.301".parse());
+        assert!("par:".parse::<ParkTarget>().is_err());
+        assert_eq!("2.301", "2.301".parse::<ParkTarget>().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_parks_override_effects() {
+        let library = Library::default();
+        let mut installation = Installation::new_from_config("tests/golden/installation.toml", &library).unwrap();
+        installation.find_element("dimmer1", "1").unwrap().set_kind(ElementKind::Intensity(1.0));
+        // As a dimmer effect blacking out the par would leave it
+        installation.find_element("par", "1").unwrap().set_kind(ElementKind::Rgbiu{
+            rgb: Color::black(),
+            i: Some(0.0),
+            uv: 0.0,
+        });
+
+        installation.park("par:1".parse().unwrap(), 0x00ff00).unwrap();
+        installation.park("2.1".parse().unwrap(), 10).unwrap();
+        installation.park("2.1".parse().unwrap(), 20).unwrap();
+        assert!(installation.park("par:9".parse().unwrap(), 0).is_err());
+        assert!(installation.park("2.513".parse().unwrap(), 0).is_err());
+
+        let chain = installation.build_dmx_chain(Instant::now());
+        assert_eq!([255, 0, 255, 0], chain[&1][20..24]);
+        assert_eq!(20, chain[&2][0]);
+        assert_eq!(2, installation.parks().len());
+
+        installation.unpark(&"2.1".parse().unwrap());
+        assert_eq!(255, installation.build_dmx_chain(Instant::now())[&2][0]);
+    }
+
+    #[test]
+    fn test_positions_cant_be_parked() {
+        let mut head = Element::new(ElementKind::Position{pan: 0.0, tilt: 0.0});
+        head.add_channel("pan", 1);
+        head.add_channel("tilt", 2);
+        let mut fixtures = HashMap::new();
+        fixtures.insert("head".to_owned(), single_element_fixture(head, 2));
+        let mut installation = Installation::new(fixtures);
+
+        assert!(installation.park("head:1".parse().unwrap(), 90).is_err());
+        assert!(installation.parks().is_empty());
+        assert!(installation.park("1.1".parse().unwrap(), 90).is_ok());
+    }
+}
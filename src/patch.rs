@@ -0,0 +1,183 @@
+use std::fmt;
+use std::str::FromStr;
+use std::collections::BTreeMap;
+
+pub const UNIVERSE_SIZE: usize = 512;
+
+/// DMX data for every patched universe, keyed by universe number.
+pub type Universes = BTreeMap<u16, Vec<u8>>;
+
+/// A patch address written as `universe.channel`, e.g. `2.301`. A bare
+/// channel number is taken to be in universe 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Address {
+    pub universe: u16,
+    pub channel: u16,
+}
+
+impl Address {
+    pub fn new(universe: u16, channel: u16) -> Self {
+        Self { universe, channel }
+    }
+
+    /// Zero-based index of the channel within its universe.
+    pub fn index(&self) -> usize {
+        self.channel as usize - 1
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |x: &str| x.trim().parse::<u16>()
+            .map_err(|_| format!("Invalid DMX address \"{}\"", s));
+
+        match s.find('.') {
+            Some(i) => Ok(Address::new(parse(&s[..i])?, parse(&s[i + 1..])?)),
+            None => Ok(Address::new(1, parse(s)?)),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{:03}", self.universe, self.channel)
+    }
+}
+
+/// A span of channels within one universe, written `1.17-1.32` or `1.17-32`.
+/// A single address is a span of one channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AddressRange {
+    pub start: Address,
+    pub end: Address,
+}
+
+impl AddressRange {
+    pub fn contains(&self, universe: u16, index: usize) -> bool {
+        let channel = index + 1;
+        universe == self.start.universe
+            && channel >= self.start.channel as usize
+            && channel <= self.end.channel as usize
+    }
+}
+
+impl FromStr for AddressRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = match s.find('-') {
+            Some(i) => (&s[..i], Some(&s[i + 1..])),
+            None => (s, None),
+        };
+
+        let start: Address = start.parse()?;
+
+        let end = match end {
+            Some(end) if end.contains('.') => end.parse()?,
+            Some(end) => {
+                let channel = end.trim().parse()
+                    .map_err(|_| format!("Invalid DMX address range \"{}\"", s))?;
+                Address::new(start.universe, channel)
+            },
+            None => start,
+        };
+
+        if end.universe != start.universe || end.channel < start.channel {
+            return Err(format!("Invalid DMX address range \"{}\"", s));
+        }
+
+        Ok(Self { start, end })
+    }
+}
+
+/// Check that each patch fits in its universe and that no two patches share
+/// a channel. Patches are `(name, address, number of channels)`.
+pub fn check_patches(patches: &[(String, Address, usize)]) -> Vec<String> {
+    let mut problems = vec![];
+
+    for (name, address, num_channels) in patches {
+        let last = address.channel as usize + (*num_channels).max(1) - 1;
+
+        if address.universe == 0 {
+            problems.push(format!("Fixture {} is patched to universe 0, universes start at 1", name));
+        }
+
+        if address.channel == 0 || last > UNIVERSE_SIZE {
+            problems.push(format!("Fixture {} at {} uses channels beyond 1-{}",
+                                  name, address, UNIVERSE_SIZE));
+        }
+    }
+
+    let mut sorted: Vec<_> = patches.iter().collect();
+    sorted.sort_by_key(|(name, address, _)| (*address, name.clone()));
+
+    // Compare against whichever earlier patch reaches furthest, so a large
+    // fixture is checked against everything it covers
+    let mut furthest: Option<(&String, &Address, usize)> = None;
+
+    for (name, address, num_channels) in sorted {
+        let end = address.channel as usize + num_channels;
+
+        if let Some((other_name, other_address, other_end)) = furthest {
+            if other_address.universe == address.universe {
+                if other_end > address.channel as usize {
+                    problems.push(format!("Fixture {} at {} overlaps fixture {} at {}",
+                                          other_name, other_address, name, address));
+                }
+
+                if other_end >= end {
+                    continue;
+                }
+            }
+        }
+
+        furthest = Some((name, address, end));
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(Ok(Address::new(2, 301)), "2.301".parse());
+        assert_eq!(Ok(Address::new(1, 69)), "69".parse());
+        assert!("2.x".parse::<Address>().is_err());
+    }
+
+    #[test]
+    fn test_parse_address_range() {
+        let range: AddressRange = "2.17-32".parse().unwrap();
+        assert_eq!(Address::new(2, 17), range.start);
+        assert_eq!(Address::new(2, 32), range.end);
+        assert!(range.contains(2, 16));
+        assert!(range.contains(2, 31));
+        assert!(!range.contains(2, 32));
+        assert!(!range.contains(1, 20));
+
+        assert_eq!(Ok(range), "2.17-2.32".parse());
+        assert!("2.5".parse::<AddressRange>().unwrap().contains(2, 4));
+        assert!("1.32-1.17".parse::<AddressRange>().is_err());
+        assert!("1.1-2.1".parse::<AddressRange>().is_err());
+    }
+
+    #[test]
+    fn test_check_patches() {
+        let patches = vec![
+            ("a".to_owned(), Address::new(1, 1), 8),
+            ("b".to_owned(), Address::new(1, 8), 8),
+            ("c".to_owned(), Address::new(2, 1), 8),
+            ("d".to_owned(), Address::new(2, 510), 8),
+        ];
+
+        let problems = check_patches(&patches);
+        assert_eq!(2, problems.len());
+        assert!(problems[0].contains("Fixture d"));
+        assert!(problems[1].contains("Fixture a at 1.001 overlaps fixture b"));
+    }
+}
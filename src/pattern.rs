@@ -1,15 +1,29 @@
 use std::fs::read_to_string;
+use std::path::{Path, PathBuf};
 use std::fmt;
 use std::collections::HashMap;
 use rlua::{Lua, Function, Table, ToLua, Context};
 
+/// Where a group element is in the installation, for patterns that work from
+/// positions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ElementPosition {
+    pub x: f32,
+    pub y: f32,
+    /// Height above the floor
+    pub z: f32,
+    /// The hanging position of the element's fixture
+    pub hang: Option<String>,
+}
+
 pub struct Pattern {
     lua: Option<Lua>,
     group: String,
     property: String,
-    script_name: String,
+    script: PathBuf,
     options: HashMap<String, toml::Value>,
     element_count: usize,
+    positions: Vec<ElementPosition>,
 }
 
 impl fmt::Debug for Pattern {
@@ -17,7 +31,7 @@ impl fmt::Debug for Pattern {
         f.debug_struct("Pattern")
             .field("group", &self.group)
             .field("property", &self.property)
-            .field("script_name", &self.script_name)
+            .field("script", &self.script)
             .finish()
     }
 }
@@ -44,38 +58,27 @@ fn toml_to_lua<'a>(toml_value: &toml::Value, ctx: Context<'a>) -> rlua::Result<r
 }
 
 impl Pattern {
-    pub fn new(script_name: &str, group: &str, property: &str, element_count: usize,
+    pub fn new(script_path: &Path, group: &str, property: &str, element_count: usize,
                options: HashMap<String, toml::Value>) -> Self
     {
-        let script = "patterns/".to_owned() + script_name;
-        let script = read_to_string(script).unwrap();
-        let lua = match Pattern::build_lua_env(&script, group, element_count, &options) {
-            Ok(lua) => Some(lua),
-            Err(e) => {
-                dbg!(e);
-                None
-            },
-        };
-
-        Self {
-            lua,
+        let mut pattern = Self {
+            lua: None,
             group: group.to_owned(),
             property: property.to_owned(),
-            script_name: script_name.to_owned(),
+            script: script_path.to_owned(),
             options,
             element_count,
-        }
+            positions: vec![],
+        };
+
+        pattern.reload();
+        pattern
     }
 
     pub fn reload(&mut self) {
-        let script = "patterns/".to_owned() + &self.script_name;
-        let script = read_to_string(script).unwrap();
-
-        let group = &self.group;
-        let element_count = self.element_count;
-        let options = &self.options;
+        let script = read_to_string(&self.script).unwrap();
 
-        self.lua = match Pattern::build_lua_env(&script, group, element_count, options) {
+        self.lua = match self.build_lua_env(&script) {
             Ok(lua) => Some(lua),
             Err(e) => {
                 dbg!(e);
@@ -84,19 +87,45 @@ impl Pattern {
         };
     }
 
-    fn build_lua_env(script: &str, group: &str, element_count: usize,
-                     options: &HashMap<String, toml::Value>) -> Result<Lua, rlua::Error>
-    {
+    /// Give the script the position of each element in its group, as the
+    /// `positions` table of `{x, y, z, hang}`, and start it again.
+    pub fn set_positions(&mut self, positions: Vec<ElementPosition>) {
+        self.positions = positions;
+        self.reload();
+    }
+
+    fn build_lua_env(&self, script: &str) -> Result<Lua, rlua::Error> {
         let lua = Lua::new();
+        let options = &self.options;
 
         lua.context(|ctx| {
             let globals = ctx.globals();
 
-            globals.set("group_name", group.to_owned())?;
-            globals.set("element_count", element_count)?;
+            // Scripts require modules from their own directory, wherever the
+            // show was started from
+            let modules = self.script.with_file_name("?.lua");
+            let package: Table = globals.get("package")?;
+            let path: String = package.get("path")?;
+            package.set("path", format!("{};{}", modules.display(), path))?;
+
+            globals.set("group_name", self.group.to_owned())?;
+            globals.set("element_count", self.element_count)?;
             let options_table = ctx.create_table()?;
             globals.set("options", options_table)?;
 
+            let positions = ctx.create_table()?;
+
+            for (i, element_position) in self.positions.iter().enumerate() {
+                let position = ctx.create_table()?;
+                position.set("x", element_position.x)?;
+                position.set("y", element_position.y)?;
+                position.set("z", element_position.z)?;
+                position.set("hang", element_position.hang.clone())?;
+                positions.set(i + 1, position)?;
+            }
+
+            globals.set("positions", positions)?;
+
             ctx.load(&script).exec()?;
 
             let setup: Function = globals.get("setup")?;
@@ -135,7 +164,7 @@ impl Pattern {
                     values = match update.call::<f32, Vec<i32>>(dt) {
                         Ok(x) => x,
                         Err(e) => {
-                            println!("Lua error in {}:update", self.script_name);
+                            println!("Lua error in {}:update", self.script.display());
                             println!("{}", e);
                             vec![]
                         }
@@ -153,6 +182,10 @@ impl Pattern {
     pub fn group(&self) -> &str {
         &self.group
     }
+
+    pub fn property(&self) -> &str {
+        &self.property
+    }
 }
 
 #[cfg(test)]
@@ -163,7 +196,30 @@ mod tests {
     fn test_pattern_creation() {
         let mut options: HashMap<String, toml::Value> = HashMap::new();
         options.insert("color".to_owned(), 0x123456.into());
-        let mut p = Pattern::new("constant.lua", "group1", "color", 2, options);
+        let mut p = Pattern::new(Path::new("patterns/constant.lua"), "group1", "color", 2, options);
         assert_eq!([0x123456, 0x123456], p.update().as_slice());
     }
+
+    #[test]
+    fn test_sweep_returns_degrees() {
+        let mut options: HashMap<String, toml::Value> = HashMap::new();
+        options.insert("center".to_owned(), 10.into());
+        options.insert("frequency".to_owned(), 0.into());
+        let mut p = Pattern::new(Path::new("patterns/sweep.lua"), "heads", "pan", 2, options);
+        assert_eq!([10, -33], p.update().as_slice());
+    }
+
+    #[test]
+    fn test_ripple_uses_positions() {
+        let mut options: HashMap<String, toml::Value> = HashMap::new();
+        options.insert("color".to_owned(), 0x0000ff.into());
+        options.insert("speed".to_owned(), 0.into());
+        options.insert("wavelength".to_owned(), 4.into());
+        let mut p = Pattern::new(Path::new("patterns/ripple.lua"), "cells", "color", 3, options);
+        p.set_positions((0..3).map(|i| ElementPosition { x: i as f32, ..Default::default() }).collect());
+        assert_eq!([128, 255, 128], p.update().as_slice());
+
+        p.reload();
+        assert_eq!([128, 255, 128], p.update().as_slice());
+    }
 }
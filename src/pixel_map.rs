@@ -0,0 +1,376 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+use std::fmt;
+use std::fs::{self, File};
+use std::path::Path;
+use std::str::FromStr;
+use image::{AnimationDecoder, RgbaImage};
+use toml::Value;
+use crate::engine::TICK_RATE;
+use crate::pattern::ElementPosition;
+
+// How long a GIF frame without a delay is shown, as browsers do
+const DEFAULT_FRAME_SECONDS: f32 = 0.1;
+
+/// Colours computed from the position in the image and the time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+    /// Every hue from left to right
+    Rainbow,
+    /// Colours swirling over the image
+    Plasma,
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rainbow" => Ok(Generator::Rainbow),
+            "plasma" => Ok(Generator::Plasma),
+            _ => Err(format!("Unknown generator {}", s)),
+        }
+    }
+}
+
+/// Where a pixel map's colours come from.
+pub enum Source {
+    Image(RgbaImage),
+    /// Images each shown for a number of seconds, then repeated
+    Frames(Vec<(RgbaImage, f32)>),
+    Generator(Generator),
+}
+
+impl fmt::Debug for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Image(image) => write!(f, "Image({}x{})", image.width(), image.height()),
+            Source::Frames(frames) => write!(f, "Frames({})", frames.len()),
+            Source::Generator(generator) => write!(f, "{:?}", generator),
+        }
+    }
+}
+
+// Full saturation and value colour for a hue from 0 to 1
+fn hue_to_rgb(hue: f32) -> (f32, f32, f32) {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+
+    match h as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    }
+}
+
+// Blend the four pixels around a point, with 0 and 1 at the centres of the
+// first and last pixels and the image tiled beyond. Transparent pixels are
+// black.
+fn sample(image: &RgbaImage, u: f32, v: f32) -> (f32, f32, f32) {
+    let (width, height) = (image.width() as i64, image.height() as i64);
+
+    if width == 0 || height == 0 {
+        return (0.0, 0.0, 0.0);
+    }
+
+    let x = u * (width - 1) as f32;
+    let y = v * (height - 1) as f32;
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let pixel = |dx: i64, dy: i64| {
+        let px = (x0 as i64 + dx).rem_euclid(width) as u32;
+        let py = (y0 as i64 + dy).rem_euclid(height) as u32;
+        let p = image.get_pixel(px, py).0;
+        let alpha = p[3] as f32 / 255.0;
+        [p[0] as f32 / 255.0 * alpha, p[1] as f32 / 255.0 * alpha, p[2] as f32 / 255.0 * alpha]
+    };
+
+    let (p00, p10, p01, p11) = (pixel(0, 0), pixel(1, 0), pixel(0, 1), pixel(1, 1));
+    let mix = |i: usize| {
+        let top = p00[i] + (p10[i] - p00[i]) * fx;
+        let bottom = p01[i] + (p11[i] - p01[i]) * fx;
+        top + (bottom - top) * fy
+    };
+
+    (mix(0), mix(1), mix(2))
+}
+
+impl Source {
+    /// A still image, or the frames of an animated GIF.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let is_gif = path.extension().filter(|ext| ext.eq_ignore_ascii_case("gif")).is_some();
+
+        if !is_gif {
+            let image = image::open(path).map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+            return Ok(Source::Image(image.to_rgba()));
+        }
+
+        let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let frames = image::gif::Decoder::new(file)
+            .and_then(|decoder| decoder.into_frames().collect_frames())
+            .map_err(|e| format!("Failed to load {}: {}", path.display(), e))?;
+
+        let frames: Vec<_> = frames.into_iter().map(|frame| {
+            let delay = frame.delay();
+            let seconds = *delay.numer() as f32 / *delay.denom() as f32 / 1000.0;
+            let seconds = if seconds > 0.0 { seconds } else { DEFAULT_FRAME_SECONDS };
+            (frame.into_buffer(), seconds)
+        }).collect();
+
+        if frames.is_empty() {
+            return Err(format!("{} has no frames", path.display()));
+        }
+
+        Ok(Source::Frames(frames))
+    }
+
+    /// Every image in a directory, in file name order, shown `fps` a second.
+    pub fn open_sequence(dir: &Path, fps: f32) -> Result<Self, String> {
+        if !(fps > 0.0 && fps.is_finite()) {
+            return Err(format!("fps should be more than 0, not {}", fps));
+        }
+
+        let mut paths: Vec<_> = fs::read_dir(dir)
+            .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+
+        if paths.is_empty() {
+            return Err(format!("{} has no frames", dir.display()));
+        }
+
+        let seconds = 1.0 / fps;
+
+        let frames = paths.iter().map(|path| {
+            image::open(path)
+                .map(|image| (image.to_rgba(), seconds))
+                .map_err(|e| format!("Failed to load {}: {}", path.display(), e))
+        }).collect::<Result<_, _>>()?;
+
+        Ok(Source::Frames(frames))
+    }
+
+    fn color_at(&self, u: f32, v: f32, t: f32) -> (f32, f32, f32) {
+        match self {
+            Source::Image(image) => sample(image, u, v),
+            Source::Frames(frames) => {
+                let total: f32 = frames.iter().map(|(_, seconds)| seconds).sum();
+                let mut at = t.rem_euclid(total.max(0.001));
+
+                for (image, seconds) in frames.iter() {
+                    if at < *seconds {
+                        return sample(image, u, v);
+                    }
+
+                    at -= seconds;
+                }
+
+                sample(&frames[frames.len() - 1].0, u, v)
+            },
+            Source::Generator(Generator::Rainbow) => hue_to_rgb(u),
+            Source::Generator(Generator::Plasma) => {
+                let swirl = (u * TAU * 2.0 + t).sin()
+                    + (v * TAU * 2.0 + t * 1.3).sin()
+                    + ((u + v) * TAU * 1.5 + t * 0.7).sin();
+                hue_to_rgb(swirl / 6.0 + 0.5)
+            },
+        }
+    }
+}
+
+// Numbers other than NaN and infinities, which would leave every element
+// sampling nowhere
+fn as_number(value: &Value) -> Option<f32> {
+    match value {
+        Value::Integer(x) => Some(*x as f32),
+        Value::Float(x) if x.is_finite() => Some(*x as f32),
+        _ => None,
+    }
+}
+
+fn number(options: &HashMap<String, Value>, name: &str) -> Result<Option<f32>, String> {
+    match options.get(name) {
+        None => Ok(None),
+        Some(value) => as_number(value).map(Some).ok_or_else(|| format!("{} should be a number", name)),
+    }
+}
+
+/// Colours a group of elements from an image laid over their positions in
+/// the installation.
+pub struct PixelMap {
+    source: Source,
+    group: String,
+    property: String,
+    /// How many times the image is enlarged over the group
+    scale: f32,
+    /// Image widths and heights moved per second
+    scroll: (f32, f32),
+    /// Degrees clockwise
+    rotation: f32,
+    /// Degrees clockwise per second
+    spin: f32,
+    // Each element's position, with the group fitted into 0 to 1
+    positions: Vec<(f32, f32)>,
+    t: f32,
+}
+
+impl fmt::Debug for PixelMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PixelMap")
+            .field("source", &self.source)
+            .field("group", &self.group)
+            .field("property", &self.property)
+            .finish()
+    }
+}
+
+impl PixelMap {
+    /// A map with `scale`, `scroll = [x, y]`, `rotation` and `spin` taken
+    /// from its options.
+    pub fn new(source: Source, group: &str, property: &str,
+               options: &HashMap<String, Value>) -> Result<Self, String>
+    {
+        let scroll = match options.get("scroll").map(|scroll| scroll.as_array().map(Vec::as_slice)) {
+            None => (0.0, 0.0),
+            Some(Some([x, y])) => match (as_number(x), as_number(y)) {
+                (Some(x), Some(y)) => (x, y),
+                _ => return Err("scroll should be [x, y]".to_owned()),
+            },
+            Some(_) => return Err("scroll should be [x, y]".to_owned()),
+        };
+
+        let scale = number(options, "scale")?.unwrap_or(1.0);
+
+        if scale <= 0.0 {
+            return Err("scale must be more than 0".to_owned());
+        }
+
+        Ok(Self {
+            source,
+            group: group.to_owned(),
+            property: property.to_owned(),
+            scale,
+            scroll,
+            rotation: number(options, "rotation")?.unwrap_or(0.0),
+            spin: number(options, "spin")?.unwrap_or(0.0),
+            positions: vec![],
+            t: 0.0,
+        })
+    }
+
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    pub fn property(&self) -> &str {
+        &self.property
+    }
+
+    /// Fit the image over the elements of the group, keeping its aspect
+    /// ratio, so it covers the longer side of the group.
+    pub fn set_positions(&mut self, positions: &[ElementPosition]) {
+        let min_x = positions.iter().map(|p| p.x).fold(f32::INFINITY, f32::min);
+        let max_x = positions.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = positions.iter().map(|p| p.y).fold(f32::INFINITY, f32::min);
+        let max_y = positions.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max);
+        let extent = (max_x - min_x).max(max_y - min_y);
+
+        self.positions = positions.iter().map(|p| {
+            if extent > 0.0 {
+                (0.5 + (p.x - (min_x + max_x) / 2.0) / extent,
+                 0.5 + (p.y - (min_y + max_y) / 2.0) / extent)
+            } else {
+                (0.5, 0.5)
+            }
+        }).collect();
+    }
+
+    /// Advance one tick and sample a colour, as 0xRRGGBB, for each element.
+    pub fn update(&mut self) -> Vec<i32> {
+        self.t += 1.0 / TICK_RATE as f32;
+        let t = self.t;
+
+        // Sample where the image has been moved from, turning the other way
+        let (sin, cos) = (-(self.rotation + self.spin * t)).to_radians().sin_cos();
+
+        self.positions.iter().map(|(x, y)| {
+            let (dx, dy) = ((x - 0.5) / self.scale, (y - 0.5) / self.scale);
+            let u = 0.5 + dx * cos - dy * sin - self.scroll.0 * t;
+            let v = 0.5 + dx * sin + dy * cos - self.scroll.1 * t;
+            let (r, g, b) = self.source.color_at(u, v, t);
+
+            let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as i32;
+            (channel(r) << 16) + (channel(g) << 8) + channel(b)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    fn row(positions: usize) -> Vec<ElementPosition> {
+        (0..positions).map(|i| ElementPosition { x: i as f32, ..Default::default() }).collect()
+    }
+
+    #[test]
+    fn test_samples_image_at_positions() {
+        let mut image = RgbaImage::new(2, 1);
+        image.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([0, 0, 255, 255]));
+
+        let mut map = PixelMap::new(Source::Image(image.clone()), "row", "color", &HashMap::new()).unwrap();
+        map.set_positions(&row(3));
+        assert_eq!(vec![0xff0000, 0x800080, 0x0000ff], map.update());
+
+        // Turned half way round, the image is the other way along the row
+        let mut options = HashMap::new();
+        options.insert("rotation".to_owned(), Value::Integer(180));
+        let mut map = PixelMap::new(Source::Image(image), "row", "color", &options).unwrap();
+        map.set_positions(&row(3));
+        assert_eq!(vec![0x0000ff, 0x800080, 0xff0000], map.update());
+
+        options.insert("scale".to_owned(), Value::Integer(0));
+        assert!(PixelMap::new(Source::Generator(Generator::Rainbow), "row", "color", &options).is_err());
+        options.insert("scale".to_owned(), Value::Float(f64::NAN));
+        assert!(PixelMap::new(Source::Generator(Generator::Rainbow), "row", "color", &options).is_err());
+
+        let mut options = HashMap::new();
+        options.insert("scroll".to_owned(), Value::Array(vec![Value::Float(f64::INFINITY),
+                                                              Value::Integer(0)]));
+        assert!(PixelMap::new(Source::Generator(Generator::Rainbow), "row", "color", &options).is_err());
+    }
+
+    #[test]
+    fn test_bad_sequences() {
+        let dir = std::env::temp_dir().join("lightboard_empty_frames");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(Err(format!("{} has no frames", dir.display())),
+                   Source::open_sequence(&dir, 25.0).map(|_| ()));
+        assert!(Source::open_sequence(&dir, 0.0).is_err());
+        assert!(Source::open_sequence(&dir, f32::NAN).is_err());
+    }
+
+    #[test]
+    fn test_frames_and_generators() {
+        let frame = |color: [u8; 4]| (RgbaImage::from_pixel(1, 1, Rgba(color)), 1.0 / TICK_RATE as f32);
+        let source = Source::Frames(vec![frame([255, 0, 0, 255]), frame([0, 255, 0, 128])]);
+        let mut map = PixelMap::new(source, "row", "color", &HashMap::new()).unwrap();
+        map.set_positions(&row(1));
+        assert_eq!(vec![0x008000], map.update());
+        assert_eq!(vec![0xff0000], map.update());
+
+        let mut map = PixelMap::new(Source::Generator(Generator::Rainbow), "row", "color",
+                                    &HashMap::new()).unwrap();
+        map.set_positions(&row(3));
+        assert_eq!(vec![0xff0000, 0x00ffff, 0xff0000], map.update());
+        assert_eq!(Ok(Generator::Plasma), "plasma".parse());
+    }
+}
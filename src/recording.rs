@@ -0,0 +1,235 @@
+use std::io::{self, Read, Write, BufReader, BufWriter};
+use std::fs::File;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::output::DmxOutput;
+use crate::patch::{Universes, UNIVERSE_SIZE};
+
+// Recordings start with this header, followed by one record per frame:
+//
+//   u32  milliseconds since the start of the recording
+//   u8   number of universes
+//   then for each universe:
+//     u16  universe
+//     u16  number of changed slots
+//     then for each change: u16 slot index, u8 value
+//
+// Changes are relative to the previous frame of the same universe, starting
+// from all zeros, so a static look costs only a few bytes per frame. All
+// integers are little-endian.
+const MAGIC: &[u8; 5] = b"LBREC";
+const VERSION: u8 = 1;
+
+/// Writes frames to a recording, timestamped from when it was created.
+pub struct Recorder<W: Write> {
+    writer: W,
+    start: Instant,
+    previous: Universes,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Recorder::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION])?;
+
+        Ok(Self {
+            writer,
+            start: Instant::now(),
+            previous: Universes::new(),
+        })
+    }
+
+    pub fn record(&mut self, at: Duration, universes: &Universes) -> io::Result<()> {
+        self.writer.write_all(&(at.as_millis() as u32).to_le_bytes())?;
+        self.writer.write_all(&[universes.len().min(255) as u8])?;
+
+        for (universe, data) in universes.iter().take(255) {
+            let previous = self.previous.entry(*universe).or_insert_with(|| vec![0; UNIVERSE_SIZE]);
+            let changes: Vec<(usize, u8)> = data.iter().enumerate().take(UNIVERSE_SIZE)
+                .filter(|(i, value)| previous[*i] != **value)
+                .map(|(i, value)| (i, *value))
+                .collect();
+
+            self.writer.write_all(&universe.to_le_bytes())?;
+            self.writer.write_all(&(changes.len() as u16).to_le_bytes())?;
+
+            for (i, value) in changes {
+                self.writer.write_all(&(i as u16).to_le_bytes())?;
+                self.writer.write_all(&[value])?;
+                previous[i] = value;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> DmxOutput for Recorder<W> {
+    fn send(&mut self, universes: &Universes) -> io::Result<()> {
+        let at = self.start.elapsed();
+        self.record(at, universes)?;
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Recording {
+    frames: Vec<(Duration, Universes)>,
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Recording {
+    pub fn load(path: &str) -> io::Result<Self> {
+        Recording::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 6];
+        reader.read_exact(&mut header)?;
+
+        if &header[..5] != MAGIC {
+            return Err(invalid("Not a lightboard recording"));
+        }
+
+        if header[5] != VERSION {
+            return Err(invalid("Unsupported recording version"));
+        }
+
+        let mut frames = vec![];
+        let mut current = Universes::new();
+
+        loop {
+            let mut time = [0; 4];
+
+            match reader.read_exact(&mut time) {
+                Ok(_) => {},
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(e) => return Err(e),
+            }
+
+            let mut count = [0; 1];
+            reader.read_exact(&mut count)?;
+            let mut frame = Universes::new();
+
+            for _ in 0..count[0] {
+                let universe = read_u16(&mut reader)?;
+                let num_changes = read_u16(&mut reader)?;
+                let data = current.entry(universe).or_insert_with(|| vec![0; UNIVERSE_SIZE]);
+
+                for _ in 0..num_changes {
+                    let i = read_u16(&mut reader)? as usize;
+                    let mut value = [0; 1];
+                    reader.read_exact(&mut value)?;
+                    *data.get_mut(i).ok_or_else(|| invalid("Slot out of range"))? = value[0];
+                }
+
+                frame.insert(universe, data.clone());
+            }
+
+            let at = Duration::from_millis(u32::from_le_bytes(time) as u64);
+            frames.push((at, frame));
+        }
+
+        Ok(Self { frames })
+    }
+
+    pub fn frames(&self) -> &[(Duration, Universes)] {
+        &self.frames
+    }
+
+    /// Send each frame at its recorded time, until the receiver goes away.
+    pub fn play(&self, send: &mpsc::Sender<Universes>, looping: bool) {
+        loop {
+            let start = Instant::now();
+
+            for (at, frame) in self.frames.iter() {
+                let elapsed = start.elapsed();
+
+                if *at > elapsed {
+                    thread::sleep(*at - elapsed);
+                }
+
+                if send.send(frame.clone()).is_err() {
+                    return;
+                }
+            }
+
+            if !looping || self.frames.is_empty() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn universes(values: &[(u16, usize, u8)]) -> Universes {
+        let mut universes = Universes::new();
+
+        for (universe, i, value) in values {
+            let data = universes.entry(*universe).or_insert_with(|| vec![0; UNIVERSE_SIZE]);
+            data[*i] = *value;
+        }
+
+        universes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let frames = vec![
+            (Duration::from_millis(0), universes(&[(1, 0, 255), (2, 511, 10)])),
+            (Duration::from_millis(25), universes(&[(1, 0, 255), (2, 511, 10)])),
+            (Duration::from_millis(50), universes(&[(1, 3, 128), (2, 511, 0)])),
+        ];
+
+        let mut recorder = Recorder::new(vec![]).unwrap();
+
+        for (at, frame) in frames.iter() {
+            recorder.record(*at, frame).unwrap();
+        }
+
+        let bytes = recorder.into_inner();
+        let recording = Recording::read(bytes.as_slice()).unwrap();
+        assert_eq!(frames.as_slice(), recording.frames());
+    }
+
+    #[test]
+    fn test_unchanged_frames_are_small() {
+        let frame = universes(&[(1, 0, 255)]);
+        let mut recorder = Recorder::new(vec![]).unwrap();
+        recorder.record(Duration::from_millis(0), &frame).unwrap();
+        let first_len = recorder.writer.len();
+        recorder.record(Duration::from_millis(25), &frame).unwrap();
+
+        assert_eq!(6 + 5 + 4 + 3, first_len);
+        assert_eq!(first_len + 5 + 4, recorder.writer.len());
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(Recording::read(&b"[fixtures]\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use std::io;
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use crate::patch::{Universes, UNIVERSE_SIZE};
+use crate::output::DmxOutput;
+use crate::dmx_input::{DmxInput, RECEIVE_TIMEOUT};
+
+pub const SACN_PORT: u16 = 5568;
+/// Priorities go from 0 to 200, with 100 the default
+pub const MAX_PRIORITY: u8 = 200;
+/// Universes go from 1 to 63999
+pub const MAX_UNIVERSE: u16 = 63999;
+
+const ACN_PACKET_IDENTIFIER: [u8; 12] = [
+    0x41, 0x53, 0x43, 0x2d, 0x45, 0x31, 0x2e, 0x31, 0x37, 0x00, 0x00, 0x00
+];
+const VECTOR_ROOT_E131_DATA: u32 = 0x0000_0004;
+const VECTOR_E131_DATA_PACKET: u32 = 0x0000_0002;
+const VECTOR_DMP_SET_PROPERTY: u8 = 0x02;
+
+const ROOT_LAYER_START: usize = 16;
+const FRAMING_LAYER_START: usize = 38;
+const DMP_LAYER_START: usize = 115;
+
+#[derive(Debug, Clone)]
+pub enum Destination {
+    Multicast,
+    Unicast(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct SacnConfig {
+    pub source_name: String,
+    pub cid: Option<[u8; 16]>,
+    pub priority: u8,
+    // sACN universe that patch universe 1 is sent on
+    pub universe: u16,
+    pub destination: Destination,
+}
+
+impl Default for SacnConfig {
+    fn default() -> Self {
+        Self {
+            source_name: "Lightboard".to_owned(),
+            cid: None,
+            priority: 100,
+            universe: 1,
+            destination: Destination::Multicast,
+        }
+    }
+}
+
+/// Streams DMX data as E1.31 (sACN) data packets, one per patched universe.
+pub struct SacnSource {
+    socket: UdpSocket,
+    config: SacnConfig,
+    cid: [u8; 16],
+    unicast: Option<SocketAddr>,
+    sequences: HashMap<u16, u8>,
+}
+
+impl SacnSource {
+    pub fn new(config: SacnConfig) -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+
+        let unicast = match &config.destination {
+            Destination::Multicast => {
+                socket.set_multicast_ttl_v4(16)?;
+                None
+            },
+            Destination::Unicast(target) => Some(resolve(target)?),
+        };
+
+        let cid = config.cid.unwrap_or_else(|| cid_from_name(&config.source_name));
+
+        Ok(Self {
+            socket,
+            config,
+            cid,
+            unicast,
+            sequences: HashMap::new(),
+        })
+    }
+
+    pub fn send_universe(&mut self, universe: u16, data: &[u8]) -> io::Result<()> {
+        let sequence = self.sequences.entry(universe).or_insert(0);
+        *sequence = sequence.wrapping_add(1);
+
+        let packet = build_data_packet(&self.cid, &self.config.source_name,
+                                       self.config.priority, *sequence, universe, data);
+
+        let target = match self.unicast {
+            Some(addr) => addr,
+            None => SocketAddr::new(multicast_addr(universe).into(), SACN_PORT),
+        };
+
+        self.socket.send_to(&packet, target)?;
+        Ok(())
+    }
+}
+
+impl DmxOutput for SacnSource {
+    /// Send every patched universe, offset by the configured first universe.
+    /// Any past the last sACN universe are left out, which check reports.
+    fn send(&mut self, universes: &Universes) -> io::Result<()> {
+        for (universe, data) in universes.iter() {
+            let universe = match self.config.universe.checked_add(universe - 1) {
+                Some(universe) if universe <= MAX_UNIVERSE => universe,
+                _ => continue,
+            };
+
+            self.send_universe(universe, data)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Receives E1.31 data packets from a console, by multicast or unicast. sACN
+/// universe `universe` is received as patch universe 1, and so on for `count`
+/// universes.
+pub struct SacnReceiver {
+    socket: UdpSocket,
+    universe: u16,
+    count: u16,
+}
+
+impl SacnReceiver {
+    pub fn new(bind: &str, universe: u16, count: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_read_timeout(Some(RECEIVE_TIMEOUT))?;
+
+        for offset in 0..count {
+            socket.join_multicast_v4(&multicast_addr(universe + offset), &Ipv4Addr::UNSPECIFIED)?;
+        }
+
+        Ok(Self { socket, universe, count })
+    }
+}
+
+impl DmxInput for SacnReceiver {
+    fn receive(&mut self) -> io::Result<Option<(u16, Vec<u8>)>> {
+        let mut buf = [0; 1024];
+
+        let len = match self.socket.recv(&mut buf) {
+            Ok(len) => len,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock
+                       || e.kind() == io::ErrorKind::TimedOut => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        match parse_data_packet(&buf[..len]) {
+            Some((universe, data)) if universe >= self.universe
+                                   && universe - self.universe < self.count => {
+                Ok(Some((universe - self.universe + 1, data.to_vec())))
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+fn resolve(addr: &str) -> io::Result<SocketAddr> {
+    let addr = if addr.contains(':') {
+        addr.to_owned()
+    } else {
+        format!("{}:{}", addr, SACN_PORT)
+    };
+
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "No address for sACN receiver")
+    })
+}
+
+pub fn multicast_addr(universe: u16) -> Ipv4Addr {
+    Ipv4Addr::new(239, 255, (universe >> 8) as u8, (universe & 0xff) as u8)
+}
+
+// A CID should stay the same between runs, so derive it from the source name
+// rather than generating a random one each time. FNV-1a is fixed, unlike the
+// standard library's hasher, so receivers see the same source after an
+// upgrade too.
+pub fn cid_from_name(name: &str) -> [u8; 16] {
+    let mut cid = [0; 16];
+
+    for (i, chunk) in cid.chunks_mut(8).enumerate() {
+        let bytes = std::iter::once(i as u8).chain(name.bytes());
+        let hash = bytes.fold(0xcbf2_9ce4_8422_2325, |hash: u64, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+        });
+        chunk.copy_from_slice(&hash.to_be_bytes());
+    }
+
+    // Mark as a version 4 (random) UUID
+    cid[6] = (cid[6] & 0x0f) | 0x40;
+    cid[8] = (cid[8] & 0x3f) | 0x80;
+    cid
+}
+
+pub fn parse_cid(s: &str) -> Option<[u8; 16]> {
+    let hex: String = s.chars().filter(|c| *c != '-').collect();
+
+    if hex.len() != 32 {
+        return None;
+    }
+
+    let mut cid = [0; 16];
+
+    for (i, byte) in cid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(cid)
+}
+
+fn flags_and_length(packet: &[u8], start: usize) -> [u8; 2] {
+    (0x7000 | (packet.len() - start) as u16).to_be_bytes()
+}
+
+pub fn build_data_packet(cid: &[u8; 16], source_name: &str, priority: u8,
+                         sequence: u8, universe: u16, data: &[u8]) -> Vec<u8>
+{
+    let data = &data[..data.len().min(UNIVERSE_SIZE)];
+    let mut packet = Vec::with_capacity(DMP_LAYER_START + 11 + data.len());
+
+    // Root layer
+    packet.extend_from_slice(&0x0010u16.to_be_bytes());     // Preamble size
+    packet.extend_from_slice(&0u16.to_be_bytes());          // Postamble size
+    packet.extend_from_slice(&ACN_PACKET_IDENTIFIER);
+    packet.extend_from_slice(&[0, 0]);                      // Flags and length
+    packet.extend_from_slice(&VECTOR_ROOT_E131_DATA.to_be_bytes());
+    packet.extend_from_slice(cid);
+
+    // Framing layer
+    packet.extend_from_slice(&[0, 0]);                      // Flags and length
+    packet.extend_from_slice(&VECTOR_E131_DATA_PACKET.to_be_bytes());
+    let mut name = source_name.as_bytes().to_vec();
+    name.truncate(63);
+    name.resize(64, 0);
+    packet.extend_from_slice(&name);
+    packet.push(priority.min(MAX_PRIORITY));
+    packet.extend_from_slice(&0u16.to_be_bytes());          // Synchronization address
+    packet.push(sequence);
+    packet.push(0);                                         // Options
+    packet.extend_from_slice(&universe.to_be_bytes());
+
+    // DMP layer
+    packet.extend_from_slice(&[0, 0]);                      // Flags and length
+    packet.push(VECTOR_DMP_SET_PROPERTY);
+    packet.push(0xa1);                                      // Address and data type
+    packet.extend_from_slice(&0u16.to_be_bytes());          // First property address
+    packet.extend_from_slice(&1u16.to_be_bytes());          // Address increment
+    packet.extend_from_slice(&(data.len() as u16 + 1).to_be_bytes());
+    packet.push(0);                                         // DMX start code
+    packet.extend_from_slice(data);
+
+    for &start in &[ROOT_LAYER_START, FRAMING_LAYER_START, DMP_LAYER_START] {
+        let field = flags_and_length(&packet, start);
+        packet[start..start + 2].copy_from_slice(&field);
+    }
+
+    packet
+}
+
+/// The universe and DMX data of a data packet. Preview data and alternate
+/// start codes are ignored.
+pub fn parse_data_packet(packet: &[u8]) -> Option<(u16, &[u8])> {
+    let be32 = |i: usize| u32::from_be_bytes([packet[i], packet[i + 1], packet[i + 2], packet[i + 3]]);
+
+    if packet.len() < DMP_LAYER_START + 11
+        || packet[4..16] != ACN_PACKET_IDENTIFIER
+        || be32(ROOT_LAYER_START + 2) != VECTOR_ROOT_E131_DATA
+        || be32(FRAMING_LAYER_START + 2) != VECTOR_E131_DATA_PACKET
+        || packet[DMP_LAYER_START + 2] != VECTOR_DMP_SET_PROPERTY
+    {
+        return None;
+    }
+
+    let options = packet[FRAMING_LAYER_START + 74];
+    let universe = u16::from_be_bytes([packet[FRAMING_LAYER_START + 75], packet[FRAMING_LAYER_START + 76]]);
+    let count = u16::from_be_bytes([packet[DMP_LAYER_START + 8], packet[DMP_LAYER_START + 9]]) as usize;
+    let start_code = packet[DMP_LAYER_START + 10];
+
+    if options & 0x80 != 0 || start_code != 0 || count == 0 {
+        return None;
+    }
+
+    let data = packet.get(DMP_LAYER_START + 11..DMP_LAYER_START + 10 + count.min(UNIVERSE_SIZE + 1))?;
+    Some((universe, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Debug)]
+    struct Decoded {
+        cid: [u8; 16],
+        source_name: String,
+        priority: u8,
+        sequence: u8,
+        universe: u16,
+        data: Vec<u8>,
+    }
+
+    fn be16(packet: &[u8], i: usize) -> u16 {
+        u16::from_be_bytes([packet[i], packet[i + 1]])
+    }
+
+    fn decode(packet: &[u8]) -> Decoded {
+        assert_eq!(ACN_PACKET_IDENTIFIER, packet[4..16]);
+        assert_eq!((packet.len() - 16) as u16, be16(packet, 16) & 0x0fff);
+        assert_eq!((packet.len() - 38) as u16, be16(packet, 38) & 0x0fff);
+        assert_eq!((packet.len() - 115) as u16, be16(packet, 115) & 0x0fff);
+        assert_eq!(0xa1, packet[118]);
+        assert_eq!(0, packet[125]);
+
+        let mut cid = [0; 16];
+        cid.copy_from_slice(&packet[22..38]);
+        let name = &packet[44..108];
+        let name_len = name.iter().position(|b| *b == 0).unwrap_or(64);
+        let count = be16(packet, 123) as usize;
+
+        Decoded {
+            cid,
+            source_name: String::from_utf8(name[..name_len].to_vec()).unwrap(),
+            priority: packet[108],
+            sequence: packet[111],
+            universe: be16(packet, 113),
+            data: packet[126..125 + count].to_vec(),
+        }
+    }
+
+    fn listener() -> (UdpSocket, String) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        (socket, addr)
+    }
+
+    fn receive(socket: &UdpSocket) -> Decoded {
+        let mut buf = [0; 1024];
+        let len = socket.recv(&mut buf).unwrap();
+        decode(&buf[..len])
+    }
+
+    #[test]
+    fn test_unicast_packets_decode() {
+        let (socket, addr) = listener();
+        let cid = parse_cid("12345678-9abc-def0-1234-56789abcdef0").unwrap();
+        let mut source = SacnSource::new(SacnConfig {
+            source_name: "Test Board".to_owned(),
+            cid: Some(cid),
+            priority: 150,
+            universe: 3,
+            destination: Destination::Unicast(addr),
+        }).unwrap();
+
+        source.send_universe(3, &[1, 2, 3]).unwrap();
+        source.send_universe(3, &[4, 5, 6]).unwrap();
+
+        let first = receive(&socket);
+        assert_eq!(cid, first.cid);
+        assert_eq!("Test Board", first.source_name);
+        assert_eq!(150, first.priority);
+        assert_eq!(3, first.universe);
+        assert_eq!(1, first.sequence);
+        assert_eq!(vec![1, 2, 3], first.data);
+
+        let second = receive(&socket);
+        assert_eq!(2, second.sequence);
+        assert_eq!(vec![4, 5, 6], second.data);
+    }
+
+    #[test]
+    fn test_each_universe_is_sent() {
+        let (socket, addr) = listener();
+        let mut source = SacnSource::new(SacnConfig {
+            universe: 10,
+            destination: Destination::Unicast(addr),
+            ..SacnConfig::default()
+        }).unwrap();
+
+        let mut universes = Universes::new();
+        universes.insert(1, vec![7; UNIVERSE_SIZE]);
+        universes.insert(2, vec![9; UNIVERSE_SIZE]);
+        source.send(&universes).unwrap();
+
+        let first = receive(&socket);
+        assert_eq!(10, first.universe);
+        assert_eq!(vec![7; UNIVERSE_SIZE], first.data);
+
+        let second = receive(&socket);
+        assert_eq!(11, second.universe);
+        assert_eq!(1, second.sequence);
+        assert_eq!(vec![9; UNIVERSE_SIZE], second.data);
+    }
+
+    #[test]
+    fn test_universes_past_the_last_are_left_out() {
+        let (socket, addr) = listener();
+        let mut source = SacnSource::new(SacnConfig {
+            universe: MAX_UNIVERSE,
+            destination: Destination::Unicast(addr),
+            ..SacnConfig::default()
+        }).unwrap();
+
+        let mut universes = Universes::new();
+        universes.insert(1, vec![7; UNIVERSE_SIZE]);
+        universes.insert(2, vec![9; UNIVERSE_SIZE]);
+        universes.insert(u16::MAX, vec![9; UNIVERSE_SIZE]);
+        source.send(&universes).unwrap();
+
+        assert_eq!(MAX_UNIVERSE, receive(&socket).universe);
+        socket.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+        assert!(socket.recv(&mut [0; 1024]).is_err());
+    }
+
+    #[test]
+    fn test_parse_data_packet() {
+        let cid = cid_from_name("Console");
+        let mut packet = build_data_packet(&cid, "Console", 100, 1, 7, &[1, 2, 3]);
+        assert_eq!(Some((7, &[1, 2, 3][..])), parse_data_packet(&packet));
+        assert_eq!(None, parse_data_packet(&packet[..120]));
+
+        // Preview data is for visualisers, not fixtures
+        packet[112] = 0x80;
+        assert_eq!(None, parse_data_packet(&packet));
+    }
+
+    #[test]
+    fn test_multicast_address() {
+        assert_eq!(Ipv4Addr::new(239, 255, 1, 2), multicast_addr(0x0102));
+    }
+
+    #[test]
+    fn test_cid_is_stable() {
+        assert_eq!(cid_from_name("Lightboard"), cid_from_name("Lightboard"));
+        assert_ne!(cid_from_name("Lightboard"), cid_from_name("Console"));
+        // Sources keep their CID across releases
+        assert_eq!(parse_cid("d479bd5f-9823-45c7-892e-22ae4408a710"), Some(cid_from_name("Lightboard")));
+    }
+}
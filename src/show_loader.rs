@@ -1,30 +1,44 @@
 use std::collections::HashMap;
 use toml::value::Value;
 use serde::Deserialize;
-use std::fs::read_to_string;
+use toml::Spanned;
+use std::path::Path;
 use crate::effect::{EffectPool, Effect, GroupMap, GroupElement, EffectElement};
 use crate::pattern::Pattern;
 use crate::cue::CueList;
+use crate::check::{self, Diagnostic};
+use crate::installation_loader;
+use crate::library::Library;
+use crate::pixel_map::{PixelMap, Source};
 
 #[derive(Deserialize, Debug)]
 struct ShowConfig {
     installation: Option<String>,
+    library: Option<LibraryConfig>,
     effects: Vec<EffectConfig>,
     groups: HashMap<String, GroupConfig>,
-    pool: HashMap<String, String>,
+    pool: HashMap<String, Spanned<String>>,
     cues: Vec<CueConfig>,
 }
 
+/// Extra directories to search for fixture definitions and patterns
+#[derive(Deserialize, Debug)]
+struct LibraryConfig {
+    fixtures: Option<Vec<String>>,
+    patterns: Option<Vec<String>>,
+}
+
 #[derive(Deserialize, Debug)]
 struct EffectConfig {
     name: String,
     elements: Option<Vec<HashMap<String, Value>>>,
     patterns: Option<Vec<HashMap<String, Value>>>,
+    maps: Option<Vec<HashMap<String, Value>>>,
 }
 
 #[derive(Deserialize, Debug)]
 struct GroupConfig {
-    elements: Vec<String>,
+    elements: Vec<Spanned<String>>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -33,87 +47,183 @@ struct CueConfig {
     command: String,
 }
 
-fn build_effect_element(config: &HashMap<String, Value>) -> Option<EffectElement> {
-    let target = match config.get("target")? {
-        Value::String(s) => s,
-        _ => return None,
+fn build_effect_element(config: &HashMap<String, Value>) -> Result<EffectElement, String> {
+    let target = match config.get("target") {
+        Some(Value::String(s)) => s,
+        _ => return Err("element has no target".to_owned()),
     };
 
-    let value = config.get("color")?;
-    let tokens: Vec<&str> = target.split(':').collect();
-    let fixture = tokens[0];
-    let element = tokens[1];
-    let property = tokens[2];
+    // Colours were the only values before other properties could be targeted
+    let value = config.get("value").or_else(|| config.get("color"))
+        .ok_or_else(|| format!("target {} has no value", target))?;
 
-    Some(EffectElement::new(fixture, element, property, value))
+    match target.split(':').collect::<Vec<_>>().as_slice() {
+        [fixture, element, property] => Ok(EffectElement::new(fixture, element, property, value)),
+        _ => Err(format!("target {} should be fixture:element:property", target)),
+    }
 }
 
-fn build_pattern(config: &mut HashMap<String, Value>, groups: &GroupMap) -> Option<Pattern> {
-    let target = match config.remove("target")? {
-        Value::String(s) => s,
-        _ => return None,
+/// A pattern, or none if its group doesn't exist
+fn build_pattern(config: &HashMap<String, Value>, groups: &GroupMap, library: &Library)
+        -> Result<Option<Pattern>, String> {
+    let target = match config.get("target") {
+        Some(Value::String(s)) => s,
+        _ => return Err("pattern has no target".to_owned()),
+    };
+
+    let (group_name, property) = group_target(target)
+        .ok_or_else(|| format!("target {} should be group:property", target))?;
+
+    let script = match config.get("script") {
+        Some(Value::String(s)) => library.find_pattern(s)?,
+        _ => return Err(format!("target {} has no script", target)),
+    };
+
+    let mut options = config.clone();
+    options.remove("target");
+    options.remove("script");
+
+    let num_group_elements = match groups.get(group_name) {
+        Some(elements) => elements.len(),
+        None => return Ok(None),
     };
 
-    let tokens: Vec<&str> = target.split(':').collect();
-    let target = tokens[0];
-    let property = tokens[1];
+    Ok(Some(Pattern::new(&script, group_name, property, num_group_elements, options)))
+}
+
+// Pattern and pixel map targets name a group after a one-letter prefix, and
+// a property
+fn group_target(target: &str) -> Option<(&str, &str)> {
+    match target.split(':').collect::<Vec<_>>().as_slice() {
+        [group, property] => Some((group.get(1..).filter(|name| !name.is_empty())?, property)),
+        _ => None,
+    }
+}
 
-    let group_name = &target[1..];  // todo Don't assume ASCII
+/// The image or animated GIF, directory of frames or generator a pixel map
+/// samples. Paths are relative to the show.
+fn build_source(config: &HashMap<String, Value>, show_dir: &Path) -> Result<Source, String> {
+    match (config.get("image"), config.get("frames"), config.get("generator")) {
+        (Some(Value::String(path)), None, None) => Source::open(&show_dir.join(path)),
+        (None, Some(Value::String(dir)), None) => {
+            let fps = match config.get("fps") {
+                None => 25.0,
+                Some(Value::Integer(fps)) => *fps as f32,
+                Some(Value::Float(fps)) => *fps as f32,
+                Some(_) => return Err("fps should be a number".to_owned()),
+            };
 
-    let script = match config.remove("script")? {
-        Value::String(s) => s,
-        _ => return None,
+            Source::open_sequence(&show_dir.join(dir), fps)
+        },
+        (None, None, Some(Value::String(name))) => Ok(Source::Generator(name.parse()?)),
+        _ => Err("needs one image, frames or generator".to_owned()),
+    }
+}
+
+fn build_pixel_map(config: &HashMap<String, Value>, show_dir: &Path) -> Result<PixelMap, String> {
+    let target = match config.get("target") {
+        Some(Value::String(target)) => target,
+        _ => return Err("has no target".to_owned()),
     };
 
-    let options = config.clone();
-    let num_group_elements = groups.get(group_name)?.len();
+    let (group, property) = group_target(target)
+        .ok_or_else(|| format!("target {} should be group:property", target))?;
 
-    Some(Pattern::new(&script, group_name, property, num_group_elements, options))
+    PixelMap::new(build_source(config, show_dir)?, group, property, config)
 }
 
-fn build_group_elements(config: &GroupConfig) -> Vec<GroupElement> {
-    config.elements.iter().map(|s| {
-        let parts: Vec<&str> = s.split(':').collect();
-        GroupElement {
-            fixture: parts[0].to_owned(),
-            element: parts[1].to_owned(),
+fn build_group_elements(config: &GroupConfig) -> Result<Vec<GroupElement>, (usize, String)> {
+    config.elements.iter().map(|member| {
+        match member.get_ref().split(':').collect::<Vec<_>>().as_slice() {
+            [fixture, element] => Ok(GroupElement {
+                fixture: (*fixture).to_owned(),
+                element: (*element).to_owned(),
+            }),
+            _ => Err((member.start(), format!("{} should be fixture:element", member.get_ref()))),
         }
     }).collect()
 }
 
-pub fn build_from_config(config_file: &str) -> EffectPool {
-    let config: ShowConfig = toml::from_str(&read_to_string(config_file).unwrap()).unwrap();
+impl ShowConfig {
+    /// Paths in a show are relative to its directory
+    fn dir(config_file: &str) -> &Path {
+        Path::new(config_file).parent().unwrap_or_else(|| Path::new(""))
+    }
 
-    let groups: GroupMap = config.groups.into_iter().map(|(name, config)| {
-        (name, build_group_elements(&config))
-    }).collect();
+    fn installation_file(&self, config_file: &str) -> String {
+        let installation = self.installation.as_deref().unwrap_or("installation.toml");
+        ShowConfig::dir(config_file).join(installation).to_string_lossy().into_owned()
+    }
+
+    fn library(&self, config_file: &str) -> Library {
+        let (fixtures, patterns) = match &self.library {
+            Some(config) => (config.fixtures.clone(), config.patterns.clone()),
+            None => (None, None),
+        };
 
-    let effects = config.effects.into_iter().map(|effect_config| {
-        let elements = effect_config.elements.unwrap_or_else(|| vec![]).iter()
-            .filter_map(|c| build_effect_element(c))
-            .collect();
+        Library::new(ShowConfig::dir(config_file),
+                     &fixtures.unwrap_or_default(), &patterns.unwrap_or_default())
+    }
+}
 
-        let patterns = effect_config.patterns.unwrap_or_else(|| vec![]).iter_mut()
-            .filter_map(|mut c| build_pattern(&mut c, &groups))
-            .collect();
+pub fn build_from_config(config_file: &str) -> Result<EffectPool, Diagnostic> {
+    let (text, config) = check::read_toml::<ShowConfig>(config_file)?;
+    let installation = config.installation_file(config_file);
+    let library = config.library(config_file);
 
-        Effect::new(&effect_config.name, 0.0, elements, patterns)
-    }).collect();
+    let groups: GroupMap = config.groups.iter().map(|(name, config)| {
+        let elements = build_group_elements(config).map_err(|(at, e)| {
+            Diagnostic::at(config_file, &text, at, &format!("Group {}: {}", name, e))
+        })?;
+
+        Ok((name.clone(), elements))
+    }).collect::<Result<_, Diagnostic>>()?;
+
+    // Problems are reported at the effect's name, found in order
+    let mut effect_at = 0;
+    let mut effects = vec![];
+
+    for effect_config in config.effects.iter() {
+        let name = &effect_config.name;
+        effect_at = check::find(&text, name, effect_at).unwrap_or(effect_at);
+        let problem = |e: String| {
+            Diagnostic::at(config_file, &text, effect_at, &format!("Effect {}: {}", name, e))
+        };
 
-    let mut pool = EffectPool::new(
-        effects, groups,
-        config.installation.unwrap_or_else(|| "installation.toml".to_owned()),
-    );
+        let elements = effect_config.elements.iter().flatten()
+            .map(build_effect_element)
+            .collect::<Result<_, _>>()
+            .map_err(problem)?;
+
+        let patterns = effect_config.patterns.iter().flatten()
+            .map(|c| build_pattern(c, &groups, &library))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(problem)?;
+
+        // Like patterns, maps of groups that don't exist are left out
+        let pixel_maps = effect_config.maps.iter().flatten()
+            .map(|c| build_pixel_map(c, ShowConfig::dir(config_file)))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| problem(format!("pixel map {}", e)))?;
+
+        let mut effect = Effect::new(name, 0.0, elements, patterns.into_iter().flatten().collect());
+        effect.set_pixel_maps(pixel_maps.into_iter()
+            .filter(|pixel_map| groups.contains_key(pixel_map.group()))
+            .collect());
+        effects.push(effect);
+    }
+
+    let mut pool = EffectPool::new(effects, groups, installation, library);
 
     for (key, effect_name) in config.pool.iter() {
-        pool.set_key(key, effect_name)
+        pool.set_key(key, effect_name.get_ref())
     }
 
-    pool
+    Ok(pool)
 }
 
-pub fn build_cue_list_from_config(config_file: &str) -> CueList {
-    let config: ShowConfig = toml::from_str(&read_to_string(config_file).unwrap()).unwrap();
+pub fn build_cue_list_from_config(config_file: &str) -> Result<CueList, Diagnostic> {
+    let (_, config) = check::read_toml::<ShowConfig>(config_file)?;
 
     let mut cue_list = CueList::new();
 
@@ -125,5 +235,149 @@ pub fn build_cue_list_from_config(config_file: &str) -> CueList {
         cue_list.add(&name, &config.command);
     }
 
-    cue_list
+    Ok(cue_list)
+}
+
+/// Checks the show, its installation and fixture definitions, adding a
+/// diagnostic for anything that wouldn't load or refers to something missing.
+pub fn check_config(config_file: &str, diagnostics: &mut Vec<Diagnostic>) {
+    let (text, config) = match check::read_toml::<ShowConfig>(config_file) {
+        Ok(config) => config,
+        Err(diagnostic) => {
+            diagnostics.push(diagnostic);
+            return;
+        }
+    };
+
+    let library = config.library(config_file);
+    let fixtures = installation_loader::check_config(
+        &config.installation_file(config_file), &library, diagnostics);
+
+    // Finds what's wrong with a fixture:element reference, if anything
+    let check_element = |fixture: &str, element: &str| -> Option<String> {
+        match fixtures.as_ref()?.get(fixture) {
+            None => Some(format!("unknown fixture {}", fixture)),
+            Some(Some(elements)) if !elements.contains_key(element) => {
+                Some(format!("fixture {} has no element {}", fixture, element))
+            },
+            Some(_) => None,
+        }
+    };
+
+    let mut groups: Vec<_> = config.groups.iter().collect();
+    groups.sort_by_key(|(name, _)| name.as_str());
+
+    for (name, group) in groups {
+        for member in group.elements.iter() {
+            let at = member.start();
+
+            let problem = match member.get_ref().split(':').collect::<Vec<_>>().as_slice() {
+                [fixture, element] => check_element(fixture, element),
+                _ => Some(format!("{} should be fixture:element", member.get_ref())),
+            };
+
+            if let Some(problem) = problem {
+                diagnostics.push(Diagnostic::at(config_file, &text, at, &format!(
+                    "Group {}: {}", name, problem)));
+            }
+        }
+    }
+
+    let check_group_target = |target: &str| match group_target(target) {
+        Some((group, _)) if config.groups.contains_key(group) => None,
+        Some(_) => Some(format!("target {} is an unknown group", target)),
+        None => Some(format!("target {} should be group:property", target)),
+    };
+
+    // Effects are found in order, then each target from its effect on
+    let mut effect_at = 0;
+
+    for effect in config.effects.iter() {
+        effect_at = check::find(&text, &effect.name, effect_at).unwrap_or(effect_at);
+        let line_of = |value: &str| {
+            check::find(&text, value, effect_at).map(|offset| check::line_of(&text, offset))
+        };
+
+        for element in effect.elements.iter().flatten() {
+            let target = match element.get("target") {
+                Some(Value::String(target)) => target,
+                _ => {
+                    diagnostics.push(Diagnostic::new(config_file, Some(check::line_of(&text, effect_at)),
+                        &format!("Effect {} has an element without a target", effect.name)));
+                    continue;
+                }
+            };
+
+            let parts: Vec<_> = target.split(':').collect();
+            let problem = match parts.as_slice() {
+                [fixture, element_name, _] => check_element(fixture, element_name),
+                _ => Some(format!("target {} should be fixture:element:property", target)),
+            }.or_else(|| match element.get("value").or_else(|| element.get("color")) {
+                // Wheel slots are picked by name
+                Some(Value::String(slot)) => {
+                    let slots = fixtures.as_ref()
+                        .and_then(|fixtures| fixtures.get(parts[0])?.as_ref()?.get(parts[1]));
+
+                    match slots {
+                        Some(slots) if !slots.contains(slot) => {
+                            Some(format!("target {} has no slot named {}", target, slot))
+                        },
+                        _ => None,
+                    }
+                },
+                Some(_) => None,
+                None => Some(format!("target {} has no value", target)),
+            });
+
+            if let Some(problem) = problem {
+                diagnostics.push(Diagnostic::new(config_file, line_of(target),
+                    &format!("Effect {}: {}", effect.name, problem)));
+            }
+        }
+
+        for pattern in effect.patterns.iter().flatten() {
+            let target = match pattern.get("target") {
+                Some(Value::String(target)) => target,
+                _ => {
+                    diagnostics.push(Diagnostic::new(config_file, Some(check::line_of(&text, effect_at)),
+                        &format!("Effect {} has a pattern without a target", effect.name)));
+                    continue;
+                }
+            };
+
+            let problem = check_group_target(target).or_else(|| match pattern.get("script") {
+                Some(Value::String(script)) => library.find_pattern(script).err(),
+                _ => Some(format!("target {} has no script", target)),
+            });
+
+            if let Some(problem) = problem {
+                diagnostics.push(Diagnostic::new(config_file, line_of(target),
+                    &format!("Effect {}: {}", effect.name, problem)));
+            }
+        }
+
+        for pixel_map in effect.maps.iter().flatten() {
+            let (line, problem) = match pixel_map.get("target") {
+                Some(Value::String(target)) => (line_of(target), check_group_target(target)),
+                _ => (Some(check::line_of(&text, effect_at)), None),
+            };
+
+            let problem = problem.or_else(|| build_pixel_map(pixel_map, ShowConfig::dir(config_file)).err());
+
+            if let Some(problem) = problem {
+                diagnostics.push(Diagnostic::new(config_file, line,
+                    &format!("Effect {}: pixel map {}", effect.name, problem)));
+            }
+        }
+    }
+
+    let mut pool: Vec<_> = config.pool.iter().collect();
+    pool.sort_by_key(|(key, _)| key.as_str());
+
+    for (key, effect_name) in pool {
+        if !config.effects.iter().any(|effect| &effect.name == effect_name.get_ref()) {
+            diagnostics.push(Diagnostic::at(config_file, &text, effect_name.start(), &format!(
+                "Pool key {} uses unknown effect {}", key, effect_name.get_ref())));
+        }
+    }
 }